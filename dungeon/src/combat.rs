@@ -1,14 +1,29 @@
+use crate::consumables::{draw_consumable_slots, ConsumableSlot};
+use crate::effects::Modifiers;
+use crate::enemy::{
+    boss_health_fraction, draw_boss_health_bar, draw_enemy, draw_health_bar, enemy_size,
+    EnemyStats, Foe,
+};
+use crate::map::LevelInfo;
+use crate::platform::{virtual_height, virtual_width, Input};
+use crate::stats::TypingEvent;
 use lazy_static::{initialize, lazy_static};
 use macroquad::prelude::*;
 use regex::Regex;
-use std::env::consts::OS;
+use serde::{Deserialize, Serialize};
 use std::time::{Duration, Instant};
 
-pub const SENTENCE_UPPER_BOUND: usize = 70;
-pub const SENTENCE_LOWER_BOUND: usize = 60;
+const REFERENCE_WPM: f32 = 40.;
+const MAX_SPEED_BONUS: f32 = 2.5;
+// Every word of the streak adds this much to the damage of the next one
+const STREAK_BONUS: f32 = 0.1;
+const MAX_STREAK_BONUS: f32 = 2.;
+
+#[derive(Serialize, Deserialize)]
 pub struct Player {
     pub health: f32,
     pub max_health: f32,
+    #[serde(skip)]
     pub sentence: Vec<char>,
     pub armoured: bool,
 }
@@ -30,10 +45,14 @@ impl Player {
     }
 }
 
+pub const FONT_SIZE: u16 = 50;
+// The fraction of the screen's width taken up by the sentence box
+const TEXT_BOX_WIDTH: f32 = 0.75;
+
 lazy_static! {
-    pub static ref MAX_LINE_LENGTH: usize = if OS == "windows" { 55 } else { 65 };
-    pub static ref FONT_SIZE: u16 = if OS == "windows" { 40 } else { 50 };
-    pub static ref CHAR_SPACING: usize = if OS == "windows" { 18 } else { 22 };
+    // Bundled so sentences are measured and drawn the same way on every platform
+    pub static ref SENTENCE_FONT: Font =
+        load_ttf_font_from_bytes(include_bytes!("../assets/DejaVuSansMono.ttf")).unwrap();
     pub static ref PLAYER_TEXTURE: Texture2D = Texture2D::from_file_with_format(
         include_bytes!("../assets/ferris-back.png"),
         Some(ImageFormat::Png),
@@ -42,75 +61,90 @@ lazy_static! {
         include_bytes!("../assets/armored_ferris-back.png"),
         Some(ImageFormat::Png),
     );
-    pub static ref ENEMY_TEXTURE: Texture2D = Texture2D::from_file_with_format(
-        include_bytes!("../assets/go-gopher.png"),
-        Some(ImageFormat::Png),
-    );
     pub static ref BASE_TEXTURE: Texture2D = Texture2D::from_file_with_format(
         include_bytes!("../assets/base.png"),
         Some(ImageFormat::Png),
     );
 }
+#[derive(Default)]
 pub enum DeletionState {
+    #[default]
     FirstCharacter,
     SecondCharacter,
     ThirdCharacter,
     EverythingElse,
 }
 
-pub enum CombatState {
-    Playing,
-    Finished,
-}
 pub async fn load_combat_textures() {
     initialize(&PLAYER_TEXTURE);
     println!("Player texture loaded");
     initialize(&ARMOURED_PLAYER_TEXTURE);
     println!("Armoured player texture loaded");
-    initialize(&ENEMY_TEXTURE);
-    println!("Enemy texture loaded");
     initialize(&BASE_TEXTURE);
     println!("Base texture loaded");
+    initialize(&SENTENCE_FONT);
+    println!("Sentence font loaded");
 }
-pub fn enter_combat_animation(_coords: (f32, f32), time: &mut Option<Instant>) -> CombatState {
-    if time.unwrap().elapsed() < Duration::from_millis(1000) {
-        draw_rectangle(0., 0., screen_width(), screen_height(), RED);
-        CombatState::Playing
-    } else {
-        CombatState::Finished
-    }
+// The red flash shown when combat starts and ends
+pub fn animation_finished(time: Option<Instant>, now: Instant) -> bool {
+    now.saturating_duration_since(time.unwrap()) >= Duration::from_millis(1000)
+}
+
+pub fn draw_combat_animation() {
+    draw_rectangle(0., 0., virtual_width(), virtual_height(), RED);
 }
 
 pub fn draw_combat_background(sky_color: &Color, ground_color: &Color) {
-    let skyline = screen_height() / 3.;
-    draw_rectangle(0., 0., screen_width(), skyline, *sky_color);
+    let skyline = virtual_height() / 3.;
+    draw_rectangle(0., 0., virtual_width(), skyline, *sky_color);
     draw_rectangle(
         0.,
         skyline,
-        screen_width(),
-        screen_height() - skyline,
+        virtual_width(),
+        virtual_height() - skyline,
         *ground_color,
     );
 }
 pub fn draw_combat(
     sentence: &[char],
-    player: &mut Player,
-    sky_color: &Color,
-    ground_color: &Color,
-) -> CombatState {
-    draw_combat_background(sky_color, ground_color);
+    player: &Player,
+    enemy: Foe,
+    enemy_health: f32,
+    streak: usize,
+    level_info: &LevelInfo,
+    consumable_slots: &[ConsumableSlot],
+) {
+    let sky_color = &level_info.sky_color;
+    draw_combat_background(sky_color, &level_info.ground_color);
     let player_sentence = &player.sentence;
     draw_text(
         &format!("Player Health: {}", player.health)[..],
-        screen_width() / 10.,
-        screen_height() * 0.9,
+        virtual_width() / 10.,
+        virtual_height() * 0.9,
         60.,
         BLACK,
     );
+    draw_health_bar(
+        virtual_width() / 10.,
+        virtual_height() * 0.9 + 20.,
+        virtual_width() / 4.,
+        player.health,
+        player.max_health,
+        Color::from_rgba(0, 182, 0, 255),
+    );
+    if streak > 1 {
+        draw_text(
+            &format!("Streak: {} words", streak),
+            virtual_width() / 10.,
+            virtual_height() * 0.9 - 60.,
+            50.,
+            BLACK,
+        );
+    }
     // Draw Enemy and base
-    let base_size = 90. + screen_width() / 6.;
+    let base_size = 90. + virtual_width() / 6.;
     let base_shrink_factor = base_size / BASE_TEXTURE.width();
-    let (enemy_x_pos, enemy_y_pos) = (screen_width() / 4., screen_height() / 2.5);
+    let (enemy_x_pos, enemy_y_pos) = (virtual_width() / 4., virtual_height() / 2.5);
     let (base_width, base_height) = (
         BASE_TEXTURE.width() * base_shrink_factor,
         BASE_TEXTURE.height() * base_shrink_factor,
@@ -126,27 +160,57 @@ pub fn draw_combat(
         },
     );
 
-    let enemy_size = screen_width() / 6.;
-    let enemy_shrink_factor = enemy_size / ENEMY_TEXTURE.width();
-    let (enemy_width, enemy_height) = (
-        ENEMY_TEXTURE.width() * enemy_shrink_factor,
-        ENEMY_TEXTURE.height() * enemy_shrink_factor,
-    );
-    draw_texture_ex(
-        *ENEMY_TEXTURE,
+    // Bosses stand out by being bigger than everything else
+    let enemy_size_on_screen = match enemy {
+        Foe::Enemy(_) => virtual_width() / 6.,
+        Foe::Boss { .. } => virtual_width() / 4.5,
+    };
+    let (enemy_width, enemy_height) = enemy_size(enemy, enemy_size_on_screen);
+    draw_enemy(
+        enemy,
         enemy_x_pos - enemy_width / 2.,
         enemy_y_pos - enemy_height / 1.2,
-        WHITE,
-        DrawTextureParams {
-            dest_size: Some(Vec2::from([enemy_width, enemy_height])),
-            flip_x: true,
-            ..Default::default()
-        },
+        enemy_width,
+        true,
     );
+    let stats = enemy.stats();
+    let (bar_x, bar_y) = (
+        enemy_x_pos - base_width / 2.,
+        enemy_y_pos + base_height / 2. + 45.,
+    );
+    match enemy {
+        Foe::Enemy(_) => {
+            draw_text(stats.name, bar_x, bar_y - 15., 40., BLACK);
+            draw_health_bar(
+                bar_x,
+                bar_y,
+                base_width,
+                enemy_health,
+                stats.health,
+                Color::from_rgba(200, 30, 30, 255),
+            );
+        }
+        Foe::Boss { boss, phase } => {
+            draw_text(
+                &format!("{} - phase {}/{}", boss.name, phase + 1, boss.phases),
+                bar_x,
+                bar_y - 15.,
+                40.,
+                BLACK,
+            );
+            draw_boss_health_bar(
+                bar_x,
+                bar_y,
+                base_width,
+                boss_health_fraction(&boss, phase, enemy_health),
+                &boss,
+            );
+        }
+    }
 
-    let base_size = 100. + screen_width() / 6.;
+    let base_size = 100. + virtual_width() / 6.;
     let base_shrink_factor = base_size / BASE_TEXTURE.width();
-    let (player_x_pos, player_y_pos) = (screen_width() * 4. / 5., screen_height() * 3. / 4.);
+    let (player_x_pos, player_y_pos) = (virtual_width() * 4. / 5., virtual_height() * 3. / 4.);
     let (base_width, base_height) = (
         BASE_TEXTURE.width() * base_shrink_factor,
         BASE_TEXTURE.height() * base_shrink_factor,
@@ -166,7 +230,7 @@ pub fn draw_combat(
     } else {
         *PLAYER_TEXTURE
     };
-    let player_size = screen_width() / 6.;
+    let player_size = virtual_width() / 6.;
     let player_shrink_factor = player_size / texture.width();
     draw_texture_ex(
         texture,
@@ -183,36 +247,36 @@ pub fn draw_combat(
     );
 
     draw_sentence(sentence, player_sentence, sky_color);
+    draw_consumable_slots(consumable_slots);
+}
 
-    if player_sentence == sentence {
-        CombatState::Finished
-    } else {
-        CombatState::Playing
-    }
+fn text_width(text: &[char]) -> f32 {
+    measure_text(
+        &text.iter().collect::<String>(),
+        Some(*SENTENCE_FONT),
+        FONT_SIZE,
+        1.,
+    )
+    .width
 }
 
-pub fn return_lines(sentence: &[char]) -> Vec<String> {
-    let string_sentence = sentence.iter().collect::<String>();
-    let words: Vec<&str> = string_sentence.split(' ').collect();
-    let mut line: Vec<&str> = Vec::new();
-    let mut temp_line = line.clone();
-    let mut lines: Vec<Vec<&str>> = Vec::new();
-    for word in words {
-        temp_line.push(word);
-        // if measure_text(&temp_line.join(" ")[..], None, *FONT_SIZE, 1.).width >= width {
-        if temp_line.join(" ").len() >= *MAX_LINE_LENGTH {
-            lines.push(line);
-            line = vec![word];
-            temp_line = line.clone();
-        } else {
-            line.push(word);
+// Splits the sentence into lines no wider than max_width, each line keeps the space after its last word
+pub fn return_lines(sentence: &[char], max_width: f32) -> Vec<&[char]> {
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    let mut line_end = 0;
+    for word in sentence.split_inclusive(|c| *c == ' ') {
+        let word_end = line_end + word.len();
+        let line = &sentence[line_start..word_end];
+        let trimmed = line.strip_suffix(&[' ']).unwrap_or(line);
+        if line_end > line_start && text_width(trimmed) > max_width {
+            lines.push(&sentence[line_start..line_end]);
+            line_start = line_end;
         }
+        line_end = word_end;
     }
-    if line.concat() != "" {
-        lines.push(line);
-    }
-
-    lines.iter().map(|line| line.join(" ")).collect()
+    lines.push(&sentence[line_start..]);
+    lines
 }
 
 fn draw_text_box(x: f32, y: f32, w: f32, h: f32, sky_color: &Color) {
@@ -266,49 +330,37 @@ fn draw_text_box(x: f32, y: f32, w: f32, h: f32, sky_color: &Color) {
     );
 }
 
-fn draw_sentence(sentence: &[char], user_sentence: &[char], sky_color: &Color) {
-    let mut char_pairs: Vec<(Option<&char>, Option<&char>)> = Vec::new();
-    let mut i = 0;
-    let text_box_width = *MAX_LINE_LENGTH as f32 * (*CHAR_SPACING as f32 + 0.5);
-    loop {
-        let char_pair = (user_sentence.get(i), sentence.get(i));
-        match char_pair {
-            (None, None) => break,
-            _ => char_pairs.push(char_pair),
-        }
-        i += 1;
-    }
-
-    let mut line_lengths: Vec<usize> = return_lines(sentence)
+pub fn draw_sentence(sentence: &[char], user_sentence: &[char], sky_color: &Color) {
+    let text_box_width = virtual_width() * TEXT_BOX_WIDTH;
+    let padding = 5.;
+    let lines = return_lines(sentence, text_box_width - 2. * padding);
+    // Anything typed past the end of the sentence carries on along the last line
+    let line_starts: Vec<usize> = lines
         .iter()
-        .map(std::string::String::len)
+        .scan(0, |start, line| {
+            let line_start = *start;
+            *start += line.len();
+            Some(line_start)
+        })
         .collect();
 
-    let last_index = line_lengths.len() - 1;
-    line_lengths[last_index] = *MAX_LINE_LENGTH;
-
-    let mut y_pos = f32::from(*FONT_SIZE) / 2. + 40.;
-    let mut num_lines = 0;
-
-    let mut num_chars = 0;
-    let mut base_x_pos = 40.;
+    let line_height = f32::from(FONT_SIZE);
+    let (box_x, box_y) = (40., 40.);
     draw_text_box(
-        base_x_pos,
-        y_pos - f32::from(*FONT_SIZE) / 2.,
+        box_x,
+        box_y,
         text_box_width,
-        f32::from(*FONT_SIZE) * line_lengths.len() as f32,
-        &sky_color,
+        line_height * lines.len() as f32,
+        sky_color,
     );
-    base_x_pos += 5.;
-    y_pos += 7.;
-    for char_pair in &char_pairs {
-        let x_pos = base_x_pos + (*CHAR_SPACING * num_chars) as f32;
-        let line_length = match line_lengths.get(num_lines) {
-            Some(length) => *length,
-            None => *MAX_LINE_LENGTH,
-        };
 
-        let (c, color) = match *char_pair {
+    let (mut x_pos, mut y_pos) = (box_x + padding, box_y + line_height * 0.75);
+    for i in 0..sentence.len().max(user_sentence.len()) {
+        if i > 0 && line_starts.contains(&i) {
+            x_pos = box_x + padding;
+            y_pos += line_height;
+        }
+        let (c, color) = match (user_sentence.get(i), sentence.get(i)) {
             (Some(c), Some(s)) => {
                 let character = if *c == ' ' { '⊔' } else { *c };
                 if c == s {
@@ -325,94 +377,136 @@ fn draw_sentence(sentence: &[char], user_sentence: &[char], sky_color: &Color) {
             (None, None) => break,
         };
         draw_text_ex(
-            &c.to_string()[..],
+            &c.to_string(),
             x_pos,
             y_pos,
             TextParams {
-                font_size: *FONT_SIZE,
+                font: *SENTENCE_FONT,
+                font_size: FONT_SIZE,
                 font_scale: 1.,
                 color,
                 ..Default::default()
             },
         );
-        num_chars += 1;
+        // Characters are placed by the width of the one in the sentence so typing doesn't shift them
+        x_pos += text_width(&[*sentence.get(i).unwrap_or(&c)]);
+    }
+}
 
-        if num_chars > line_length {
-            num_chars = 0;
-            num_lines += 1;
-            y_pos += f32::from(*FONT_SIZE);
-        }
+// How many characters from the start of the sentence have been typed without a mistake
+pub fn correct_prefix(sentence: &[char], user_sentence: &[char]) -> usize {
+    sentence
+        .iter()
+        .zip(user_sentence)
+        .take_while(|(expected, typed)| expected == typed)
+        .count()
+}
+
+// Words typed correctly from the start, a word counts once the space after it is typed
+pub fn completed_words(sentence: &[char], user_sentence: &[char]) -> usize {
+    let correct = correct_prefix(sentence, user_sentence);
+    let spaces = sentence[..correct].iter().filter(|c| **c == ' ').count();
+    if correct == sentence.len() {
+        spaces + 1
+    } else {
+        spaces
     }
 }
 
+pub fn num_words(sentence: &[char]) -> usize {
+    sentence.iter().filter(|c| **c == ' ').count() + 1
+}
+
+// The damage a completed word does, base_damage is enough to finish the enemy by the end of the
+// sentence so typing faster than REFERENCE_WPM or keeping a streak finishes it sooner
+pub fn word_damage(base_damage: f32, wpm: f32, streak: usize) -> f32 {
+    let speed_bonus = (wpm / REFERENCE_WPM).clamp(1., MAX_SPEED_BONUS);
+    let streak_bonus = (1. + STREAK_BONUS * streak as f32).min(MAX_STREAK_BONUS);
+    base_damage * speed_bonus * streak_bonus
+}
+
+// Whether the enemy attacked
 pub fn enemy_attack(
     player: &mut Player,
+    enemy: &EnemyStats,
     last_attack: &mut Instant,
-    damage_reduction: &f32,
-    damage_percentage: &f32,
-) {
-    let enemy_attack_time = Duration::from_millis(2000);
-    if last_attack.elapsed() >= enemy_attack_time {
-        let damage = (3. * damage_percentage) - damage_reduction;
-        if damage > 0. {
-            player.health -= damage;
-        }
-        *last_attack = Instant::now();
+    modifiers: &Modifiers,
+    now: Instant,
+) -> bool {
+    if now.saturating_duration_since(*last_attack)
+        >= modifiers.attack_interval(enemy.attack_interval)
+    {
+        enemy_hit(player, enemy, modifiers);
+        *last_attack = now;
+        return true;
     }
+    false
+}
+
+pub fn enemy_hit(player: &mut Player, enemy: &EnemyStats, modifiers: &Modifiers) {
+    player.health -= modifiers.damage(enemy.damage);
 }
 
 pub fn typing(
+    input: &mut impl Input,
     user_sentence: &mut Vec<char>,
     deletion_state: &mut DeletionState,
     time_since_last_delete: &mut Instant,
-) {
-    if let Some(c) = get_char_pressed() {
+    now: Instant,
+) -> Vec<TypingEvent> {
+    let mut events = Vec::new();
+    if let Some(c) = input.char_pressed() {
         if Regex::new(r"[\x20-\x7e]")
             .unwrap()
             .is_match(&c.to_string()[..])
         {
+            events.push(TypingEvent::Typed(c, user_sentence.len()));
             user_sentence.push(c);
         }
     }
-    if is_key_released(KeyCode::Backspace) {
+    if input.key_released(KeyCode::Backspace) {
         *deletion_state = DeletionState::FirstCharacter;
     }
-    if is_key_down(KeyCode::Backspace) {
+    if input.key_down(KeyCode::Backspace) {
         match deletion_state {
             DeletionState::FirstCharacter => {
-                user_sentence.pop();
+                delete_character(user_sentence, &mut events);
                 *deletion_state = DeletionState::SecondCharacter;
-                *time_since_last_delete = Instant::now();
+                *time_since_last_delete = now;
             }
             DeletionState::SecondCharacter => {
-                if time_since_last_delete.elapsed() > Duration::from_millis(400) {
-                    user_sentence.pop();
+                if now.saturating_duration_since(*time_since_last_delete)
+                    > Duration::from_millis(400)
+                {
+                    delete_character(user_sentence, &mut events);
                     *deletion_state = DeletionState::ThirdCharacter;
-                    *time_since_last_delete = Instant::now();
+                    *time_since_last_delete = now;
                 }
             }
             DeletionState::ThirdCharacter => {
-                if time_since_last_delete.elapsed() > Duration::from_millis(150) {
-                    user_sentence.pop();
+                if now.saturating_duration_since(*time_since_last_delete)
+                    > Duration::from_millis(150)
+                {
+                    delete_character(user_sentence, &mut events);
                     *deletion_state = DeletionState::EverythingElse;
-                    *time_since_last_delete = Instant::now();
+                    *time_since_last_delete = now;
                 }
             }
             DeletionState::EverythingElse => {
-                if time_since_last_delete.elapsed() > Duration::from_millis(50) {
-                    user_sentence.pop();
-                    *time_since_last_delete = Instant::now();
+                if now.saturating_duration_since(*time_since_last_delete)
+                    > Duration::from_millis(50)
+                {
+                    delete_character(user_sentence, &mut events);
+                    *time_since_last_delete = now;
                 }
             }
         }
     }
+    events
 }
 
-pub fn exit_combat_animation(_coords: (f32, f32), time: &mut Option<Instant>) -> CombatState {
-    if time.unwrap().elapsed() < Duration::from_millis(1000) {
-        draw_rectangle(0., 0., screen_width(), screen_height(), RED);
-        CombatState::Playing
-    } else {
-        CombatState::Finished
+fn delete_character(user_sentence: &mut Vec<char>, events: &mut Vec<TypingEvent>) {
+    if user_sentence.pop().is_some() {
+        events.push(TypingEvent::Deleted);
     }
 }
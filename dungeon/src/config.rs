@@ -0,0 +1,349 @@
+use crate::platform::{virtual_height, virtual_width, Input};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+const SETTINGS_FILE: &str = "settings.json";
+const MENU_FONT_SIZE: u16 = 50;
+const DETAIL_FONT_SIZE: u16 = 30;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    // Uses the values in the custom section of the settings file
+    Custom,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Custom,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Custom => "Custom",
+        }
+    }
+}
+
+// Every knob that changes how hard a run is
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct GameConfig {
+    // The map is a grid_size by grid_size grid
+    pub grid_size: usize,
+    // Fractions of the nodes that hold an enemy or a treasure chest
+    pub enemy_ratio: f32,
+    pub treasure_ratio: f32,
+    // Multiplies the length of every sentence
+    pub sentence_length_scale: f32,
+    // Characters added to every sentence on each level after the first
+    pub level_sentence_growth: usize,
+    // Multiplies the time between enemy attacks, bigger is slower
+    pub attack_interval_scale: f32,
+    pub damage_scale: f32,
+    // Only show the nodes next to where the player has been
+    pub fog_of_war: bool,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            grid_size: 6,
+            enemy_ratio: 0.2,
+            treasure_ratio: 0.1,
+            sentence_length_scale: 1.,
+            level_sentence_growth: 10,
+            attack_interval_scale: 1.,
+            damage_scale: 1.,
+            fog_of_war: false,
+        }
+    }
+}
+
+impl GameConfig {
+    pub fn easy() -> Self {
+        GameConfig {
+            grid_size: 5,
+            enemy_ratio: 0.15,
+            treasure_ratio: 0.15,
+            sentence_length_scale: 0.7,
+            level_sentence_growth: 5,
+            attack_interval_scale: 1.5,
+            damage_scale: 0.6,
+            fog_of_war: false,
+        }
+    }
+
+    pub fn hard() -> Self {
+        GameConfig {
+            grid_size: 7,
+            enemy_ratio: 0.3,
+            treasure_ratio: 0.07,
+            sentence_length_scale: 1.2,
+            level_sentence_growth: 15,
+            attack_interval_scale: 0.7,
+            damage_scale: 1.5,
+            fog_of_war: true,
+        }
+    }
+
+    // Brings every knob into a range a run can be played with, anything that isn't a number
+    // goes back to the default
+    pub fn clamped(self) -> Self {
+        let default = GameConfig::default();
+        let within = |value: f32, min: f32, max: f32, default: f32| {
+            if value.is_nan() {
+                default
+            } else {
+                value.clamp(min, max)
+            }
+        };
+        GameConfig {
+            grid_size: self.grid_size.clamp(2, 12),
+            enemy_ratio: within(self.enemy_ratio, 0., 0.6, default.enemy_ratio),
+            treasure_ratio: within(self.treasure_ratio, 0., 0.5, default.treasure_ratio),
+            sentence_length_scale: within(
+                self.sentence_length_scale,
+                0.1,
+                3.,
+                default.sentence_length_scale,
+            ),
+            level_sentence_growth: self.level_sentence_growth.min(50),
+            attack_interval_scale: within(
+                self.attack_interval_scale,
+                0.1,
+                10.,
+                default.attack_interval_scale,
+            ),
+            damage_scale: within(self.damage_scale, 0., 10., default.damage_scale),
+            fog_of_war: self.fog_of_war,
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "{0}x{0} map{6}, {1:.0}% enemies, sentences x{2:.1} (+{3} a level), attacks every x{4:.1}, damage x{5:.1}",
+            self.grid_size,
+            self.enemy_ratio * 100.,
+            self.sentence_length_scale,
+            self.level_sentence_growth,
+            self.attack_interval_scale,
+            self.damage_scale,
+            if self.fog_of_war { " with fog" } else { "" }
+        )
+    }
+}
+
+// What is kept in the settings file, edit the custom section and choose Custom to use it
+#[derive(Serialize, Deserialize, Default)]
+pub struct Settings {
+    pub difficulty: Difficulty,
+    #[serde(default)]
+    pub custom: GameConfig,
+    // Otherwise the game opens in a window that can be resized
+    #[serde(default)]
+    pub fullscreen: bool,
+    // Move on the map by typing the word next to a neighbouring node
+    #[serde(default)]
+    pub word_travel: bool,
+}
+
+impl Settings {
+    pub fn load(data_dir: &Path) -> Settings {
+        match fs::read_to_string(data_dir.join(SETTINGS_FILE)) {
+            Ok(contents) => {
+                let mut settings: Settings =
+                    serde_json::from_str(&contents).unwrap_or_else(|error| {
+                        println!("Failed to read the settings: {}", error);
+                        Settings::default()
+                    });
+                // The custom difficulty is edited by hand so it could be anything
+                settings.custom = settings.custom.clamped();
+                settings
+            }
+            Err(_) => {
+                // Write the defaults out so there is a file to edit
+                let settings = Settings::default();
+                settings.save(data_dir);
+                settings
+            }
+        }
+    }
+
+    pub fn save(&self, data_dir: &Path) {
+        match serde_json::to_string_pretty(self) {
+            Ok(contents) => {
+                if let Err(error) = fs::write(data_dir.join(SETTINGS_FILE), contents) {
+                    println!("Failed to write the settings: {}", error);
+                }
+            }
+            Err(error) => println!("Failed to serialize the settings: {}", error),
+        }
+    }
+
+    pub fn config(&self, difficulty: Difficulty) -> GameConfig {
+        match difficulty {
+            Difficulty::Easy => GameConfig::easy(),
+            Difficulty::Normal => GameConfig::default(),
+            Difficulty::Hard => GameConfig::hard(),
+            Difficulty::Custom => self.custom,
+        }
+    }
+}
+
+fn settings_menu_position(index: usize, (screen_width, screen_height): (f32, f32)) -> (f32, f32) {
+    (
+        screen_width / 8.,
+        screen_height / 4. + (index as f32 + 1.) * 2.5 * f32::from(MENU_FONT_SIZE),
+    )
+}
+
+pub fn draw_settings_menu(settings: &Settings) {
+    clear_background(BLACK);
+    let title = "Choose a difficulty";
+    draw_text(
+        title,
+        virtual_width() / 2. - measure_text(title, None, MENU_FONT_SIZE, 1.).width / 2.,
+        virtual_height() / 4.,
+        f32::from(MENU_FONT_SIZE),
+        WHITE,
+    );
+    for (i, difficulty) in Difficulty::ALL.iter().enumerate() {
+        let (x, y) = settings_menu_position(i, (virtual_width(), virtual_height()));
+        let color = if *difficulty == settings.difficulty {
+            YELLOW
+        } else {
+            WHITE
+        };
+        draw_text(
+            &format!("{}. {}", i + 1, difficulty.name()),
+            x,
+            y,
+            f32::from(MENU_FONT_SIZE),
+            color,
+        );
+        draw_text(
+            &settings.config(*difficulty).summary(),
+            x,
+            y + f32::from(DETAIL_FONT_SIZE),
+            f32::from(DETAIL_FONT_SIZE),
+            GRAY,
+        );
+    }
+    let fullscreen = format!(
+        "F: fullscreen {} (used the next time the game starts)",
+        if settings.fullscreen { "on" } else { "off" }
+    );
+    draw_text(
+        &fullscreen,
+        virtual_width() / 2. - measure_text(&fullscreen, None, DETAIL_FONT_SIZE, 1.).width / 2.,
+        virtual_height() - 60. - 2. * f32::from(DETAIL_FONT_SIZE),
+        f32::from(DETAIL_FONT_SIZE),
+        WHITE,
+    );
+    let word_travel = format!(
+        "W: travel by typing words {}",
+        if settings.word_travel { "on" } else { "off" }
+    );
+    draw_text(
+        &word_travel,
+        virtual_width() / 2. - measure_text(&word_travel, None, DETAIL_FONT_SIZE, 1.).width / 2.,
+        virtual_height() - 60. - 3.5 * f32::from(DETAIL_FONT_SIZE),
+        f32::from(DETAIL_FONT_SIZE),
+        WHITE,
+    );
+    let prompt = format!("The custom difficulty can be changed in {}", SETTINGS_FILE);
+    draw_text(
+        &prompt,
+        virtual_width() / 2. - measure_text(&prompt, None, DETAIL_FONT_SIZE, 1.).width / 2.,
+        virtual_height() - 60.,
+        f32::from(DETAIL_FONT_SIZE),
+        GRAY,
+    );
+}
+
+// Moves the highlight and returns true once the player confirms a difficulty
+pub fn settings_select(input: &impl Input, settings: &mut Settings) -> bool {
+    let number_keys = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4];
+    let selected = Difficulty::ALL
+        .iter()
+        .position(|difficulty| *difficulty == settings.difficulty)
+        .unwrap_or_default();
+    let num_difficulties = Difficulty::ALL.len();
+    if input.key_pressed(KeyCode::F) {
+        settings.fullscreen = !settings.fullscreen;
+    }
+    if input.key_pressed(KeyCode::W) {
+        settings.word_travel = !settings.word_travel;
+    }
+    for (i, key) in number_keys.iter().enumerate() {
+        if input.key_pressed(*key) {
+            settings.difficulty = Difficulty::ALL[i];
+            return true;
+        }
+    }
+    if input.key_pressed(KeyCode::Down) {
+        settings.difficulty = Difficulty::ALL[(selected + 1) % num_difficulties];
+    }
+    if input.key_pressed(KeyCode::Up) {
+        settings.difficulty = Difficulty::ALL[(selected + num_difficulties - 1) % num_difficulties];
+    }
+    if input.key_pressed(KeyCode::Enter) {
+        return true;
+    }
+    if let Some((_, mouse_y)) = input.mouse_clicked() {
+        for i in 0..num_difficulties {
+            let (_, y) = settings_menu_position(i, input.screen_size());
+            if mouse_y <= y + f32::from(DETAIL_FONT_SIZE)
+                && mouse_y >= y - f32::from(MENU_FONT_SIZE)
+            {
+                settings.difficulty = Difficulty::ALL[i];
+                return true;
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_custom_difficulty_out_of_range_is_clamped() {
+        let data_dir =
+            std::env::temp_dir().join(format!("dungeon-settings-{}", std::process::id()));
+        fs::create_dir_all(&data_dir).unwrap();
+        fs::write(
+            data_dir.join(SETTINGS_FILE),
+            r#"{"difficulty": "Custom", "custom": {"grid_size": 1000, "attack_interval_scale": -2, "damage_scale": 1e40}}"#,
+        )
+        .unwrap();
+        let settings = Settings::load(&data_dir);
+        fs::remove_dir_all(&data_dir).unwrap();
+        let config = settings.config(Difficulty::Custom);
+        assert_eq!(config.grid_size, 12);
+        assert_eq!(config.attack_interval_scale, 0.1);
+        assert_eq!(config.damage_scale, 10.);
+        // What wasn't given is the default
+        assert_eq!(config.enemy_ratio, GameConfig::default().enemy_ratio);
+
+        let config = GameConfig {
+            sentence_length_scale: f32::NAN,
+            ..GameConfig::default()
+        }
+        .clamped();
+        assert_eq!(config.sentence_length_scale, 1.);
+    }
+}
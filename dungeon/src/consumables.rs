@@ -0,0 +1,271 @@
+use crate::effects::{Effect, Lasting};
+use crate::platform::{virtual_height, virtual_width, Input};
+use ::rand::seq::SliceRandom;
+use macroquad::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// The most of each kind that can be carried
+pub const MAX_HELD: usize = 3;
+const SLOT_SIZE: f32 = 90.;
+
+// Single use items found in chests and used during combat
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Consumable {
+    Potion,
+    Shield,
+    SkipWord,
+    SlowTime,
+}
+
+impl Consumable {
+    // In the order of their slots
+    pub const ALL: [Consumable; 4] = [
+        Consumable::Potion,
+        Consumable::Shield,
+        Consumable::SkipWord,
+        Consumable::SlowTime,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Consumable::Potion => "Potion",
+            Consumable::Shield => "Shield",
+            Consumable::SkipWord => "Skip Word",
+            Consumable::SlowTime => "Slow Time",
+        }
+    }
+
+    pub fn key(&self) -> KeyCode {
+        match self {
+            Consumable::Potion => KeyCode::F1,
+            Consumable::Shield => KeyCode::F2,
+            Consumable::SkipWord => KeyCode::F3,
+            Consumable::SlowTime => KeyCode::F4,
+        }
+    }
+
+    // Can be typed instead of pressing the key
+    pub fn command(&self) -> &'static str {
+        match self {
+            Consumable::Potion => "/heal",
+            Consumable::Shield => "/shield",
+            Consumable::SkipWord => "/skip",
+            Consumable::SlowTime => "/slow",
+        }
+    }
+
+    // How long before another of the same kind can be used
+    pub fn cooldown(&self) -> Duration {
+        match self {
+            Consumable::Potion => Duration::from_secs(10),
+            Consumable::Shield => Duration::from_secs(15),
+            Consumable::SkipWord => Duration::from_secs(5),
+            Consumable::SlowTime => Duration::from_secs(20),
+        }
+    }
+
+    // Skipping a word changes the sentence rather than the player, so it has no effect
+    pub fn effect(&self) -> Option<Effect> {
+        match self {
+            Consumable::Potion => Some(Effect::Heal { amount: 30. }),
+            Consumable::Shield => Some(Effect::Armour {
+                amount: 3.,
+                lasting: Lasting::NextBrawl,
+            }),
+            Consumable::SkipWord => None,
+            Consumable::SlowTime => Some(Effect::AttackInterval {
+                multiplier: 2.,
+                lasting: Lasting::NextBrawl,
+            }),
+        }
+    }
+}
+
+// What a slot shows
+pub struct ConsumableSlot {
+    pub consumable: Consumable,
+    pub count: usize,
+    // The fraction of the cooldown left
+    pub cooldown: f32,
+}
+
+// What the player is carrying and when each kind can next be used
+#[derive(Serialize, Deserialize, Default)]
+pub struct Consumables {
+    pub held: HashMap<Consumable, usize>,
+    #[serde(skip)]
+    ready_at: HashMap<Consumable, Instant>,
+}
+
+impl Consumables {
+    pub fn count(&self, consumable: Consumable) -> usize {
+        self.held.get(&consumable).copied().unwrap_or(0)
+    }
+
+    // Whether there was room for it
+    pub fn add(&mut self, consumable: Consumable) -> bool {
+        let count = self.held.entry(consumable).or_insert(0);
+        if *count < MAX_HELD {
+            *count += 1;
+            return true;
+        }
+        false
+    }
+
+    // How much of the cooldown is left, from 1 just after use down to 0 when it's ready
+    pub fn cooldown_left(&self, consumable: Consumable, now: Instant) -> f32 {
+        match self.ready_at.get(&consumable) {
+            Some(ready_at) => {
+                ready_at.saturating_duration_since(now).as_secs_f32()
+                    / consumable.cooldown().as_secs_f32()
+            }
+            None => 0.,
+        }
+    }
+
+    // Takes one out of the inventory if there is one and it isn't cooling down
+    pub fn take(&mut self, consumable: Consumable, now: Instant) -> bool {
+        if self.count(consumable) == 0 || self.cooldown_left(consumable, now) > 0. {
+            return false;
+        }
+        self.held.insert(consumable, self.count(consumable) - 1);
+        self.ready_at
+            .insert(consumable, now + consumable.cooldown());
+        true
+    }
+
+    // Cooldowns don't run down while the game is paused
+    pub fn shift(&mut self, paused_for: Duration) {
+        for ready_at in self.ready_at.values_mut() {
+            *ready_at += paused_for;
+        }
+    }
+
+    // Cooldowns don't carry over between brawls
+    pub fn end_brawl(&mut self) {
+        self.ready_at.clear();
+    }
+
+    pub fn slots(&self, now: Instant) -> Vec<ConsumableSlot> {
+        Consumable::ALL
+            .into_iter()
+            .map(|consumable| ConsumableSlot {
+                consumable,
+                count: self.count(consumable),
+                cooldown: self.cooldown_left(consumable, now),
+            })
+            .collect()
+    }
+}
+
+pub fn random_consumable(rng: &mut ChaCha8Rng) -> Consumable {
+    *Consumable::ALL.choose(rng).unwrap()
+}
+
+// The consumable whose key was pressed
+pub fn consumable_pressed(input: &impl Input) -> Option<Consumable> {
+    Consumable::ALL
+        .into_iter()
+        .find(|consumable| input.key_pressed(consumable.key()))
+}
+
+// Whether what was typed after the last correct character could still become a command
+pub fn command_prefix(typed: &[char]) -> bool {
+    let typed: String = typed.iter().collect();
+    Consumable::ALL
+        .iter()
+        .any(|consumable| consumable.command().starts_with(&typed))
+}
+
+pub fn typed_command(typed: &[char]) -> Option<Consumable> {
+    let typed: String = typed.iter().collect();
+    Consumable::ALL
+        .into_iter()
+        .find(|consumable| consumable.command() == typed)
+}
+
+// Where the sentence will be once the word being typed is skipped, including the space after it
+pub fn skip_word(sentence: &[char], correct: usize) -> usize {
+    // Between words the next one is skipped
+    let start = if sentence.get(correct) == Some(&' ') {
+        correct + 1
+    } else {
+        correct
+    };
+    match sentence[start.min(sentence.len())..]
+        .iter()
+        .position(|c| *c == ' ')
+    {
+        Some(space) => start + space + 1,
+        None => sentence.len(),
+    }
+}
+
+// A slot for each kind along the bottom of the screen, with how many there are and a shadow
+// over the ones cooling down
+pub fn draw_consumable_slots(slots: &[ConsumableSlot]) {
+    let spacing = SLOT_SIZE * 1.2;
+    let x = virtual_width() / 2. - spacing * slots.len() as f32 / 2.;
+    let y = virtual_height() - SLOT_SIZE - 30.;
+    for (i, slot) in slots.iter().enumerate() {
+        let slot_x = x + i as f32 * spacing;
+        draw_rectangle(slot_x, y, SLOT_SIZE, SLOT_SIZE, WHITE);
+        if slot.cooldown > 0. {
+            draw_rectangle(
+                slot_x,
+                y + SLOT_SIZE * (1. - slot.cooldown),
+                SLOT_SIZE,
+                SLOT_SIZE * slot.cooldown,
+                Color::from_rgba(0, 0, 0, 120),
+            );
+        }
+        draw_rectangle_lines(slot_x, y, SLOT_SIZE, SLOT_SIZE, 4., BLACK);
+        let color = if slot.count > 0 { BLACK } else { GRAY };
+        draw_text(&format!("F{}", i + 1), slot_x + 8., y + 28., 28., color);
+        draw_text(
+            &format!("x{}", slot.count),
+            slot_x + SLOT_SIZE - 40.,
+            y + 28.,
+            28.,
+            color,
+        );
+        draw_text(
+            slot.consumable.name(),
+            slot_x + 6.,
+            y + SLOT_SIZE - 12.,
+            20.,
+            color,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consumables_wait_for_their_cooldown() {
+        let mut consumables = Consumables::default();
+        for _ in 0..MAX_HELD {
+            assert!(consumables.add(Consumable::Potion));
+        }
+        assert!(!consumables.add(Consumable::Potion));
+        let now = Instant::now();
+        assert!(consumables.take(Consumable::Potion, now));
+        assert!(!consumables.take(Consumable::Potion, now));
+        assert_eq!(consumables.cooldown_left(Consumable::Potion, now), 1.);
+        // Time spent paused is added back on
+        let later = now + Consumable::Potion.cooldown();
+        consumables.shift(Duration::from_secs(1));
+        assert!(!consumables.take(Consumable::Potion, later));
+        assert!(consumables.take(Consumable::Potion, later + Duration::from_secs(1)));
+        // A new brawl starts with nothing cooling down
+        consumables.end_brawl();
+        assert!(consumables.take(Consumable::Potion, later));
+        assert_eq!(consumables.count(Consumable::Potion), 0);
+        assert!(!consumables.take(Consumable::SkipWord, later));
+    }
+}
@@ -0,0 +1,414 @@
+use crate::enemy::EnemyKind;
+use crate::level::{LevelFile, LevelNode, LevelTile};
+use crate::map::{Background, Graph, NODE_SIZE};
+use crate::platform::{virtual_height, virtual_width, Input};
+use macroquad::prelude::*;
+
+pub const EDITOR_LEVEL_PATH: &str = "levels/custom.json";
+const DEFAULT_GRID_SIZE: usize = 6;
+const MAX_GRID_SIZE: usize = 12;
+const FONT_SIZE: u16 = 30;
+// The keys that put each kind of enemy on the selected node
+const ENEMY_KEYS: [(KeyCode, EnemyKind); 4] = [
+    (KeyCode::Key1, EnemyKind::Gopher),
+    (KeyCode::Key2, EnemyKind::Swarm),
+    (KeyCode::Key3, EnemyKind::Brute),
+    (KeyCode::Key4, EnemyKind::Mirror),
+];
+
+// A level being built by hand, the start and goal can be missing until it is saved or played
+pub struct Editor {
+    pub name: String,
+    pub background: Background,
+    pub ground_color: [u8; 3],
+    pub sky_color: [u8; 3],
+    pub grid_size: usize,
+    pub nodes: Vec<LevelNode>,
+    pub edges: Vec<(usize, usize)>,
+    pub start: Option<usize>,
+    pub goal: Option<usize>,
+    pub selected: Option<usize>,
+    // The node an edge is being dragged from
+    pub dragging_from: Option<usize>,
+    // The route from the start to the goal, shown once the level has been checked
+    pub route: Vec<usize>,
+    pub message: String,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        let background = Background::Forest;
+        let (ground_color, sky_color) = default_colors(background);
+        Editor {
+            name: "Custom".to_string(),
+            background,
+            ground_color,
+            sky_color,
+            grid_size: DEFAULT_GRID_SIZE,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            start: None,
+            goal: None,
+            selected: None,
+            dragging_from: None,
+            route: Vec::new(),
+            message: "Click to place a node".to_string(),
+        }
+    }
+}
+
+// What the game should do once the editor is done with
+pub enum EditorAction {
+    Play(LevelFile),
+    Quit,
+}
+
+// The colours the built in level with this background uses
+fn default_colors(background: Background) -> ([u8; 3], [u8; 3]) {
+    let to_rgb = |color: Color| {
+        [
+            (color.r * 255.) as u8,
+            (color.g * 255.) as u8,
+            (color.b * 255.) as u8,
+        ]
+    };
+    Graph::levels()
+        .into_iter()
+        .find(|level| level.background == background)
+        .map(|level| (to_rgb(level.ground_color), to_rgb(level.sky_color)))
+        .unwrap_or(([0, 0, 0], [0, 0, 0]))
+}
+
+// Where the centre of a grid cell is drawn, the same place the map draws a node there
+pub fn cell_position(
+    (x, y): (isize, isize),
+    grid_size: usize,
+    (screen_width, screen_height): (f32, f32),
+) -> (f32, f32) {
+    (
+        x as f32 * screen_width / grid_size as f32 + NODE_SIZE / 2.,
+        y as f32 * screen_height / grid_size as f32 + NODE_SIZE / 2.,
+    )
+}
+
+// The grid cell under a position, if it is close enough to the cell's centre
+fn cell_at(
+    (x, y): (f32, f32),
+    grid_size: usize,
+    screen_size: (f32, f32),
+) -> Option<(isize, isize)> {
+    let cell = (
+        ((x - NODE_SIZE / 2.) * grid_size as f32 / screen_size.0).round() as isize,
+        ((y - NODE_SIZE / 2.) * grid_size as f32 / screen_size.1).round() as isize,
+    );
+    let on_grid = |value: isize| (0..grid_size as isize).contains(&value);
+    let (centre_x, centre_y) = cell_position(cell, grid_size, screen_size);
+    (on_grid(cell.0)
+        && on_grid(cell.1)
+        && (x - centre_x).powi(2) + (y - centre_y).powi(2) <= (NODE_SIZE / 2.).powi(2))
+    .then_some(cell)
+}
+
+impl Editor {
+    pub fn from_level(level: LevelFile) -> Self {
+        Editor {
+            name: level.name,
+            background: level.background,
+            ground_color: level.ground_color,
+            sky_color: level.sky_color,
+            grid_size: level.grid_size,
+            nodes: level.nodes,
+            edges: level.edges,
+            start: Some(level.start),
+            goal: Some(level.goal),
+            ..Editor::default()
+        }
+    }
+
+    // The level as it would be saved, as long as it can be played
+    pub fn level(&self) -> Result<LevelFile, String> {
+        let (Some(start), Some(goal)) = (self.start, self.goal) else {
+            return Err("The level needs a start (S) and a goal (G)".to_string());
+        };
+        let level = LevelFile {
+            name: self.name.clone(),
+            background: self.background,
+            ground_color: self.ground_color,
+            sky_color: self.sky_color,
+            grid_size: self.grid_size,
+            nodes: self.nodes.clone(),
+            edges: self.edges.clone(),
+            start,
+            goal,
+            fog_of_war: false,
+        };
+        level.validate()?;
+        Ok(level)
+    }
+
+    fn node_at(&self, cell: (isize, isize)) -> Option<usize> {
+        self.nodes.iter().position(|node| (node.x, node.y) == cell)
+    }
+
+    fn toggle_edge(&mut self, node_1: usize, node_2: usize) {
+        let edge = (node_1.min(node_2), node_1.max(node_2));
+        if self.edges.contains(&edge) {
+            self.edges.retain(|other| *other != edge);
+        } else {
+            self.edges.push(edge);
+        }
+    }
+
+    // Removes a node and its edges, everything after it moves down an index
+    fn remove_node(&mut self, index: usize) {
+        self.nodes.remove(index);
+        let shift = |node: usize| if node > index { node - 1 } else { node };
+        self.edges = self
+            .edges
+            .iter()
+            .filter(|(node_1, node_2)| *node_1 != index && *node_2 != index)
+            .map(|(node_1, node_2)| (shift(*node_1), shift(*node_2)))
+            .collect();
+        self.start = self.start.filter(|start| *start != index).map(shift);
+        self.goal = self.goal.filter(|goal| *goal != index).map(shift);
+        self.selected = None;
+    }
+
+    // Checks the level and shows the route from the start to the goal
+    fn check(&mut self) -> Option<LevelFile> {
+        match self.level() {
+            Ok(level) => {
+                let graph = Graph::from_level(0, level.clone());
+                self.route = graph.get_path(level.goal, level.start);
+                self.message = format!(
+                    "The level is ready, the shortest route is {} steps",
+                    self.route.len() - 1
+                );
+                Some(level)
+            }
+            Err(error) => {
+                self.route.clear();
+                self.message = error;
+                None
+            }
+        }
+    }
+
+    fn edited(&mut self) {
+        // The route might not be there any more
+        self.route.clear();
+    }
+}
+
+pub fn editor_events(editor: &mut Editor, input: &mut impl Input) -> Option<EditorAction> {
+    while input.char_pressed().is_some() {}
+    let screen_size = input.screen_size();
+    if let Some(position) = input.mouse_clicked() {
+        match cell_at(position, editor.grid_size, screen_size) {
+            Some(cell) => match editor.node_at(cell) {
+                Some(node) => {
+                    editor.selected = Some(node);
+                    editor.dragging_from = Some(node);
+                }
+                None => {
+                    editor.nodes.push(LevelNode {
+                        x: cell.0,
+                        y: cell.1,
+                        tile: LevelTile::Empty,
+                    });
+                    editor.selected = Some(editor.nodes.len() - 1);
+                    editor.edited();
+                }
+            },
+            None => editor.selected = None,
+        }
+    }
+    if let Some(position) = input.mouse_released() {
+        let target =
+            cell_at(position, editor.grid_size, screen_size).and_then(|cell| editor.node_at(cell));
+        if let (Some(from), Some(to)) = (editor.dragging_from.take(), target) {
+            if from != to {
+                editor.toggle_edge(from, to);
+                editor.edited();
+            }
+        }
+    }
+
+    if let Some(selected) = editor.selected {
+        let mut tile = None;
+        if input.key_pressed(KeyCode::T) {
+            tile = Some(LevelTile::Treasure);
+        }
+        if input.key_pressed(KeyCode::E) {
+            tile = Some(LevelTile::Empty);
+        }
+        if input.key_pressed(KeyCode::M) {
+            tile = Some(LevelTile::Shop);
+        }
+        for (key, kind) in ENEMY_KEYS {
+            if input.key_pressed(key) {
+                tile = Some(LevelTile::Enemy(kind));
+            }
+        }
+        // The start and goal are always empty
+        if input.key_pressed(KeyCode::S) {
+            editor.start = Some(selected);
+            tile = Some(LevelTile::Empty);
+        }
+        if input.key_pressed(KeyCode::G) {
+            editor.goal = Some(selected);
+            tile = Some(LevelTile::Empty);
+        }
+        if let Some(tile) = tile {
+            if tile != LevelTile::Empty {
+                editor.start = editor.start.filter(|start| *start != selected);
+                editor.goal = editor.goal.filter(|goal| *goal != selected);
+            }
+            editor.nodes[selected].tile = tile;
+            editor.edited();
+        }
+        if input.key_pressed(KeyCode::Delete) || input.key_pressed(KeyCode::Backspace) {
+            editor.remove_node(selected);
+            editor.edited();
+        }
+    }
+
+    if input.key_pressed(KeyCode::B) {
+        let next = Background::ALL
+            .iter()
+            .position(|background| *background == editor.background)
+            .map_or(0, |index| (index + 1) % Background::ALL.len());
+        editor.background = Background::ALL[next];
+        (editor.ground_color, editor.sky_color) = default_colors(editor.background);
+    }
+    // The grid can't shrink past any node
+    if input.key_pressed(KeyCode::Up) && editor.grid_size < MAX_GRID_SIZE {
+        editor.grid_size += 1;
+    }
+    if input.key_pressed(KeyCode::Down)
+        && editor.grid_size > 2
+        && editor.nodes.iter().all(|node| {
+            node.x < editor.grid_size as isize - 1 && node.y < editor.grid_size as isize - 1
+        })
+    {
+        editor.grid_size -= 1;
+    }
+
+    if input.key_pressed(KeyCode::V) {
+        editor.check();
+    }
+    if input.key_pressed(KeyCode::Enter) {
+        if let Some(level) = editor.check() {
+            editor.message = match level.save(EDITOR_LEVEL_PATH) {
+                Ok(()) => format!("Saved to {}", EDITOR_LEVEL_PATH),
+                Err(error) => format!("Failed to save: {}", error),
+            };
+        }
+    }
+    if input.key_pressed(KeyCode::L) {
+        match LevelFile::load(EDITOR_LEVEL_PATH) {
+            Ok(level) => {
+                *editor = Editor::from_level(level);
+                editor.message = format!("Loaded {}", EDITOR_LEVEL_PATH);
+            }
+            Err(error) => editor.message = format!("Failed to load: {}", error),
+        }
+    }
+    if input.key_pressed(KeyCode::P) {
+        if let Some(level) = editor.check() {
+            return Some(EditorAction::Play(level));
+        }
+    }
+    if input.key_pressed(KeyCode::Escape) {
+        return Some(EditorAction::Quit);
+    }
+    None
+}
+
+pub fn draw_editor(editor: &Editor, mouse: (f32, f32)) {
+    let [r, g, b] = editor.ground_color;
+    clear_background(Color::from_rgba(r, g, b, 255));
+    let screen_size = (virtual_width(), virtual_height());
+    let position = |index: usize| {
+        let node = &editor.nodes[index];
+        cell_position((node.x, node.y), editor.grid_size, screen_size)
+    };
+    // Nodes near the bottom are drawn over the help
+    let lines = [
+        format!(
+            "{} by {} grid (up/down), background {:?} (B)",
+            editor.grid_size, editor.grid_size, editor.background
+        ),
+        "Click to place or select a node, drag between nodes to join or split them".to_string(),
+        "Selected node: S start, G goal, T chest, M shop, 1-4 enemy, E empty, delete removes it"
+            .to_string(),
+        format!(
+            "V check, enter save and L load {}, P play, escape back",
+            EDITOR_LEVEL_PATH
+        ),
+        editor.message.clone(),
+    ];
+    let font_size = f32::from(FONT_SIZE);
+    let top = virtual_height() - font_size * (lines.len() as f32 + 0.5);
+    draw_rectangle(
+        0.,
+        top - font_size,
+        virtual_width(),
+        virtual_height() - top + font_size,
+        Color::from_rgba(0, 0, 0, 180),
+    );
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            line,
+            20.,
+            top + i as f32 * font_size,
+            font_size,
+            if i == lines.len() - 1 { YELLOW } else { WHITE },
+        );
+    }
+    for x in 0..editor.grid_size as isize {
+        for y in 0..editor.grid_size as isize {
+            let (x, y) = cell_position((x, y), editor.grid_size, screen_size);
+            draw_circle(x, y, 4., Color::from_rgba(0, 0, 0, 100));
+        }
+    }
+    for (node_1, node_2) in &editor.edges {
+        let ((x_1, y_1), (x_2, y_2)) = (position(*node_1), position(*node_2));
+        draw_line(x_1, y_1, x_2, y_2, 10., Color::from_rgba(0, 0, 0, 130));
+    }
+    for pair in editor.route.windows(2) {
+        let ((x_1, y_1), (x_2, y_2)) = (position(pair[0]), position(pair[1]));
+        draw_line(x_1, y_1, x_2, y_2, 6., YELLOW);
+    }
+    if let Some(from) = editor.dragging_from {
+        let (x, y) = position(from);
+        draw_line(x, y, mouse.0, mouse.1, 4., WHITE);
+    }
+    for (index, node) in editor.nodes.iter().enumerate() {
+        let (x, y) = position(index);
+        let (label, color) = if editor.start == Some(index) {
+            ("Start".to_string(), SKYBLUE)
+        } else if editor.goal == Some(index) {
+            ("Goal".to_string(), GOLD)
+        } else {
+            match node.tile {
+                LevelTile::Empty => (String::new(), LIGHTGRAY),
+                LevelTile::Treasure => ("Chest".to_string(), ORANGE),
+                LevelTile::Shop => ("Shop".to_string(), GOLD),
+                LevelTile::Enemy(kind) => (format!("{:?}", kind), RED),
+            }
+        };
+        draw_circle(x, y, NODE_SIZE / 3., color);
+        if editor.selected == Some(index) {
+            draw_circle_lines(x, y, NODE_SIZE / 2.5, 4., WHITE);
+        }
+        let width = measure_text(&label, None, FONT_SIZE, 1.).width;
+        draw_text(
+            &label,
+            x - width / 2.,
+            y + NODE_SIZE / 2. + f32::from(FONT_SIZE) / 2.,
+            f32::from(FONT_SIZE),
+            BLACK,
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use crate::combat::Player;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Lasting {
+    // Until the end of the next brawl
+    NextBrawl,
+    // Until the end of the run
+    Run,
+}
+
+// The building blocks of what cards do, multipliers below 1 make things smaller
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Effect {
+    // Restores health, up to the player's max health
+    Heal { amount: f32 },
+    MaxHealth { multiplier: f32 },
+    // Taken off the damage of every enemy attack
+    Armour { amount: f32, lasting: Lasting },
+    DamageTaken { multiplier: f32, lasting: Lasting },
+    SentenceLength { multiplier: f32, lasting: Lasting },
+    // Multiplies the time between enemy attacks
+    AttackInterval { multiplier: f32, lasting: Lasting },
+}
+
+impl Effect {
+    // Whether the effect lasts beyond the next brawl, healing doesn't count
+    pub fn permanent(&self) -> bool {
+        match self {
+            Effect::Heal { .. } => false,
+            Effect::MaxHealth { .. } => true,
+            Effect::Armour { lasting, .. }
+            | Effect::DamageTaken { lasting, .. }
+            | Effect::SentenceLength { lasting, .. }
+            | Effect::AttackInterval { lasting, .. } => *lasting == Lasting::Run,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Modifiers {
+    pub armour: f32,
+    pub damage_taken: f32,
+    pub sentence_length: f32,
+    pub attack_interval: f32,
+}
+
+impl Default for Modifiers {
+    fn default() -> Self {
+        Modifiers {
+            armour: 0.,
+            damage_taken: 1.,
+            sentence_length: 1.,
+            attack_interval: 1.,
+        }
+    }
+}
+
+impl Modifiers {
+    fn combine(&self, other: &Modifiers) -> Modifiers {
+        Modifiers {
+            armour: self.armour + other.armour,
+            damage_taken: self.damage_taken * other.damage_taken,
+            sentence_length: self.sentence_length * other.sentence_length,
+            attack_interval: self.attack_interval * other.attack_interval,
+        }
+    }
+
+    // The damage an enemy attack does to the player
+    pub fn damage(&self, damage: f32) -> f32 {
+        (damage * self.damage_taken - self.armour).max(0.)
+    }
+
+    pub fn sentence_length(&self, length: usize) -> usize {
+        (length as f32 * self.sentence_length).floor() as usize
+    }
+
+    pub fn attack_interval(&self, interval: Duration) -> Duration {
+        interval.mul_f32(self.attack_interval)
+    }
+}
+
+// Everything the cards picked this run are doing to it
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub struct CardEffects {
+    pub next_brawl: Modifiers,
+    pub run: Modifiers,
+}
+
+impl CardEffects {
+    fn modifiers(&mut self, lasting: &Lasting) -> &mut Modifiers {
+        match lasting {
+            Lasting::NextBrawl => &mut self.next_brawl,
+            Lasting::Run => &mut self.run,
+        }
+    }
+
+    pub fn apply(&mut self, effect: &Effect, player: &mut Player) {
+        match effect {
+            Effect::Heal { amount } => {
+                player.health = (player.health + amount).min(player.max_health)
+            }
+            Effect::MaxHealth { multiplier } => player.max_health *= multiplier,
+            Effect::Armour { amount, lasting } => self.modifiers(lasting).armour += amount,
+            Effect::DamageTaken {
+                multiplier,
+                lasting,
+            } => self.modifiers(lasting).damage_taken *= multiplier,
+            Effect::SentenceLength {
+                multiplier,
+                lasting,
+            } => self.modifiers(lasting).sentence_length *= multiplier,
+            Effect::AttackInterval {
+                multiplier,
+                lasting,
+            } => self.modifiers(lasting).attack_interval *= multiplier,
+        }
+        player.armoured = self.current().armour > 0.;
+    }
+
+    // What applies to the next brawl
+    pub fn current(&self) -> Modifiers {
+        self.run.combine(&self.next_brawl)
+    }
+
+    pub fn end_brawl(&mut self, player: &mut Player) {
+        self.next_brawl = Modifiers::default();
+        player.armoured = self.current().armour > 0.;
+    }
+}
@@ -1,6 +1,14 @@
+use crate::map::draw_seed;
+use crate::platform::{virtual_height, virtual_width, Input};
+use crate::relics::{draw_relic_list, Relic};
+use crate::stats::{run_summary_lines, CombatStats};
 use lazy_static::{initialize, lazy_static};
 use macroquad::prelude::*;
 const FERRIS_SIZE: f32 = 500.;
+const ENEMY_POINTS: usize = 100;
+const LEVEL_POINTS: usize = 1000;
+// Earned for exploring the whole of a level before beating its boss
+const EXPLORATION_POINTS: f32 = 500.;
 
 lazy_static! {
     pub static ref DEAD_FERRIS_TEXTURE: Texture2D = Texture2D::from_file_with_format(
@@ -20,14 +28,72 @@ pub async fn load_end_images() {
     println!("Loaded success image");
 }
 
-pub fn draw_death_screen(num_levels: &usize, num_enemies_defeated: &usize) {
+fn level_score(explored: f32) -> usize {
+    LEVEL_POINTS + (explored * EXPLORATION_POINTS).round() as usize
+}
+
+// Points for every enemy beaten and every level finished, more the more of it was explored
+pub fn run_score(num_enemies_defeated: usize, explored: &[f32]) -> usize {
+    num_enemies_defeated * ENEMY_POINTS + explored.iter().copied().map(level_score).sum::<usize>()
+}
+
+// Shown above the cards when a level is finished
+pub fn draw_level_summary(level: usize, explored: f32) {
+    let text = format!(
+        "Level {} cleared, {:.0}% explored: +{} points",
+        level,
+        explored * 100.,
+        level_score(explored)
+    );
+    let font_size = 50;
+    let size = measure_text(&text, None, font_size, 1.);
+    let (x, y) = (virtual_width() / 2. - size.width / 2., 80.);
+    draw_rectangle(
+        x - 20.,
+        y - size.offset_y - 15.,
+        size.width + 40.,
+        size.height + 30.,
+        Color::from_rgba(0, 0, 0, 200),
+    );
+    draw_text(&text, x, y, f32::from(font_size), GOLD);
+}
+
+// Draws the score and typing stats of the run underneath the headline
+fn draw_run_stats(combat_stats: &[CombatStats], score: usize, font_size: u16) {
+    let score_line = format!("Score: {}", score);
+    let lines = std::iter::once(score_line).chain(run_summary_lines(combat_stats));
+    for (i, text) in lines.enumerate() {
+        draw_text_ex(
+            &text,
+            virtual_width() / 2. - measure_text(&text, None, font_size, 1.).width / 2.,
+            virtual_height() / 5. + (i + 1) as f32 * font_size as f32,
+            TextParams {
+                font_size,
+                font_scale: 1.0,
+                color: LIGHTGRAY,
+                font_scale_aspect: 1.0,
+                ..Default::default()
+            },
+        );
+    }
+}
+
+pub fn draw_death_screen(
+    num_levels: &usize,
+    num_enemies_defeated: &usize,
+    seed: &u64,
+    combat_stats: &[CombatStats],
+    score: usize,
+    relics: &[Relic],
+) {
     clear_background(BLACK);
+    draw_seed(*seed, WHITE);
     let ferris_shrink_factor = FERRIS_SIZE / DEAD_FERRIS_TEXTURE.width();
     let font_size = 40;
     draw_text_ex(
         "You died :(",
-        screen_width() / 2. - measure_text("You died :(", None, font_size, 1.).width / 2.,
-        screen_height() / 5. - font_size as f32,
+        virtual_width() / 2. - measure_text("You died :(", None, font_size, 1.).width / 2.,
+        virtual_height() / 5. - font_size as f32,
         TextParams {
             font_size,
             font_scale: 1.0,
@@ -42,8 +108,8 @@ pub fn draw_death_screen(num_levels: &usize, num_enemies_defeated: &usize) {
     );
     draw_text_ex(
         &text_to_draw,
-        screen_width() / 2. - measure_text(&text_to_draw, None, font_size, 1.).width / 2.,
-        screen_height() / 5.,
+        virtual_width() / 2. - measure_text(&text_to_draw, None, font_size, 1.).width / 2.,
+        virtual_height() / 5.,
         TextParams {
             font_size,
             font_scale: 1.0,
@@ -52,10 +118,12 @@ pub fn draw_death_screen(num_levels: &usize, num_enemies_defeated: &usize) {
             ..Default::default()
         },
     );
+    draw_run_stats(combat_stats, score, 30);
+    draw_relic_list(relics, 4. * virtual_height() / 5. - (3 * font_size) as f32);
     draw_texture_ex(
         *DEAD_FERRIS_TEXTURE,
-        screen_width() / 2. - DEAD_FERRIS_TEXTURE.width() * ferris_shrink_factor / 2.,
-        screen_height() / 2. - DEAD_FERRIS_TEXTURE.height() * ferris_shrink_factor / 2.,
+        virtual_width() / 2. - DEAD_FERRIS_TEXTURE.width() * ferris_shrink_factor / 2.,
+        virtual_height() / 2. - DEAD_FERRIS_TEXTURE.height() * ferris_shrink_factor / 2.,
         WHITE,
         DrawTextureParams {
             dest_size: Some(Vec2::from([
@@ -67,10 +135,17 @@ pub fn draw_death_screen(num_levels: &usize, num_enemies_defeated: &usize) {
     );
 
     draw_text_ex(
-        "Press 'r' to play again",
-        screen_width() / 2.
-            - measure_text("Press 'r' to play again", None, font_size, 1.).width / 2.,
-        4. * screen_height() / 5.,
+        "Press 'r' to go back to the title screen",
+        virtual_width() / 2.
+            - measure_text(
+                "Press 'r' to go back to the title screen",
+                None,
+                font_size,
+                1.,
+            )
+            .width
+                / 2.,
+        4. * virtual_height() / 5.,
         TextParams {
             font_size,
             font_scale: 1.0,
@@ -83,8 +158,8 @@ pub fn draw_death_screen(num_levels: &usize, num_enemies_defeated: &usize) {
     let text = "Credit goes to; Renee French for the Go Gopher enemy design";
     draw_text_ex(
         text,
-        screen_width() / 2. - measure_text(text, None, font_size, 1.).width / 2.,
-        4. * screen_height() / 5. - (2 * font_size) as f32,
+        virtual_width() / 2. - measure_text(text, None, font_size, 1.).width / 2.,
+        4. * virtual_height() / 5. - (2 * font_size) as f32,
         TextParams {
             font_size,
             font_scale: 1.0,
@@ -98,8 +173,8 @@ pub fn draw_death_screen(num_levels: &usize, num_enemies_defeated: &usize) {
         "Karen Rustad Tölva for the design of the Ferris the crab, and Nim lang for the crown";
     draw_text_ex(
         text,
-        screen_width() / 2. - measure_text(text, None, font_size, 1.).width / 2.,
-        4. * screen_height() / 5. - font_size as f32,
+        virtual_width() / 2. - measure_text(text, None, font_size, 1.).width / 2.,
+        4. * virtual_height() / 5. - font_size as f32,
         TextParams {
             font_size,
             font_scale: 1.0,
@@ -110,8 +185,15 @@ pub fn draw_death_screen(num_levels: &usize, num_enemies_defeated: &usize) {
     );
 }
 
-pub fn draw_victory_screen(num_enemies_defeated: &usize) {
+pub fn draw_victory_screen(
+    num_enemies_defeated: &usize,
+    seed: &u64,
+    combat_stats: &[CombatStats],
+    score: usize,
+    relics: &[Relic],
+) {
     clear_background(BLACK);
+    draw_seed(*seed, WHITE);
     let ferris_shrink_factor = FERRIS_SIZE / DEAD_FERRIS_TEXTURE.width();
     let font_size = 40;
     let text_to_draw = format!(
@@ -120,8 +202,8 @@ pub fn draw_victory_screen(num_enemies_defeated: &usize) {
     );
     draw_text_ex(
         &text_to_draw,
-        screen_width() / 2. - measure_text(&text_to_draw, None, font_size, 1.).width / 2.,
-        screen_height() / 5.,
+        virtual_width() / 2. - measure_text(&text_to_draw, None, font_size, 1.).width / 2.,
+        virtual_height() / 5.,
         TextParams {
             font_size,
             font_scale: 1.0,
@@ -130,10 +212,12 @@ pub fn draw_victory_screen(num_enemies_defeated: &usize) {
             ..Default::default()
         },
     );
+    draw_run_stats(combat_stats, score, 30);
+    draw_relic_list(relics, 4. * virtual_height() / 5. - (3 * font_size) as f32);
     draw_texture_ex(
         *VICTORIOUS_FERRIS_TEXTURE,
-        screen_width() / 2. - VICTORIOUS_FERRIS_TEXTURE.width() * ferris_shrink_factor / 2.,
-        screen_height() / 2. - VICTORIOUS_FERRIS_TEXTURE.height() * ferris_shrink_factor / 2.,
+        virtual_width() / 2. - VICTORIOUS_FERRIS_TEXTURE.width() * ferris_shrink_factor / 2.,
+        virtual_height() / 2. - VICTORIOUS_FERRIS_TEXTURE.height() * ferris_shrink_factor / 2.,
         WHITE,
         DrawTextureParams {
             dest_size: Some(Vec2::from([
@@ -144,10 +228,17 @@ pub fn draw_victory_screen(num_enemies_defeated: &usize) {
         },
     );
     draw_text_ex(
-        "Press 'r' to play again",
-        screen_width() / 2.
-            - measure_text("Press 'r' to play again", None, font_size, 1.).width / 2.,
-        4. * screen_height() / 5.,
+        "Press 'r' to go back to the title screen",
+        virtual_width() / 2.
+            - measure_text(
+                "Press 'r' to go back to the title screen",
+                None,
+                font_size,
+                1.,
+            )
+            .width
+                / 2.,
+        4. * virtual_height() / 5.,
         TextParams {
             font_size,
             font_scale: 1.0,
@@ -160,8 +251,8 @@ pub fn draw_victory_screen(num_enemies_defeated: &usize) {
     let text = "Credit goes to; Renee French for the Go Gopher enemy design";
     draw_text_ex(
         text,
-        screen_width() / 2. - measure_text(text, None, font_size, 1.).width / 2.,
-        4. * screen_height() / 5. - (2 * font_size) as f32,
+        virtual_width() / 2. - measure_text(text, None, font_size, 1.).width / 2.,
+        4. * virtual_height() / 5. - (2 * font_size) as f32,
         TextParams {
             font_size,
             font_scale: 1.0,
@@ -175,8 +266,8 @@ pub fn draw_victory_screen(num_enemies_defeated: &usize) {
         "Karen Rustad Tölva for the design of the Ferris the crab, and Nim lang for the crown";
     draw_text_ex(
         text,
-        screen_width() / 2. - measure_text(text, None, font_size, 1.).width / 2.,
-        4. * screen_height() / 5. - font_size as f32,
+        virtual_width() / 2. - measure_text(text, None, font_size, 1.).width / 2.,
+        4. * virtual_height() / 5. - font_size as f32,
         TextParams {
             font_size,
             font_scale: 1.0,
@@ -187,6 +278,6 @@ pub fn draw_victory_screen(num_enemies_defeated: &usize) {
     );
 }
 
-pub fn restart() -> bool {
-    is_key_pressed(KeyCode::R)
+pub fn restart(input: &impl Input) -> bool {
+    input.key_pressed(KeyCode::R)
 }
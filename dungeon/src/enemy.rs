@@ -0,0 +1,272 @@
+use crate::config::GameConfig;
+use ::rand::{seq::SliceRandom, Rng};
+use lazy_static::{initialize, lazy_static};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+lazy_static! {
+    pub static ref GOPHER_TEXTURE: Texture2D = Texture2D::from_file_with_format(
+        include_bytes!("../assets/go-gopher.png"),
+        Some(ImageFormat::Png),
+    );
+    pub static ref BEETLE_TEXTURE: Texture2D = {
+        let texture = Texture2D::from_file_with_format(
+            include_bytes!("../assets/beetle.png"),
+            Some(ImageFormat::Png),
+        );
+        // Keep the pixel art sharp when it is scaled up
+        texture.set_filter(FilterMode::Nearest);
+        texture
+    };
+    pub static ref CREATURES_TEXTURE: Texture2D = {
+        let texture = Texture2D::from_file_with_format(
+            include_bytes!("../assets/roguelikecreatures.png"),
+            Some(ImageFormat::Png),
+        );
+        texture.set_filter(FilterMode::Nearest);
+        texture
+    };
+}
+
+pub async fn load_enemy_textures() {
+    initialize(&GOPHER_TEXTURE);
+    println!("Gopher texture loaded");
+    initialize(&BEETLE_TEXTURE);
+    println!("Beetle texture loaded");
+    initialize(&CREATURES_TEXTURE);
+    println!("Creature textures loaded");
+}
+
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum EnemyKind {
+    Gopher,
+    // Fast, weak attacks and short sentences
+    Swarm,
+    // Slow, heavy attacks and long sentences
+    Brute,
+    // Punctuation heavy sentences, getting the punctuation wrong is punished straight away
+    Mirror,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChallengeStyle {
+    Standard,
+    Short,
+    Long,
+    Punctuation,
+}
+
+pub struct EnemyStats {
+    pub name: &'static str,
+    pub attack_interval: Duration,
+    pub damage: f32,
+    pub health: f32,
+    pub challenge: ChallengeStyle,
+}
+
+impl EnemyStats {
+    // Applies the difficulty of the run
+    pub fn scaled(self, config: &GameConfig) -> EnemyStats {
+        EnemyStats {
+            attack_interval: self.attack_interval.mul_f32(config.attack_interval_scale),
+            damage: self.damage * config.damage_scale,
+            ..self
+        }
+    }
+}
+
+impl ChallengeStyle {
+    // The range of sentence lengths before the level and card modifiers are applied
+    pub fn sentence_lengths(&self) -> std::ops::Range<usize> {
+        match self {
+            ChallengeStyle::Standard | ChallengeStyle::Punctuation => 60..70,
+            ChallengeStyle::Short => 30..40,
+            ChallengeStyle::Long => 75..90,
+        }
+    }
+
+    pub fn accepts(&self, sentence: &str) -> bool {
+        match self {
+            ChallengeStyle::Punctuation => {
+                sentence
+                    .chars()
+                    .filter(|c| c.is_ascii_punctuation())
+                    .count()
+                    >= 3
+            }
+            _ => true,
+        }
+    }
+}
+
+impl EnemyKind {
+    pub fn random<R: Rng>(rng: &mut R) -> EnemyKind {
+        [
+            (EnemyKind::Gopher, 4),
+            (EnemyKind::Swarm, 2),
+            (EnemyKind::Brute, 2),
+            (EnemyKind::Mirror, 1),
+        ]
+        .choose_weighted(rng, |(_, weight)| *weight)
+        .unwrap()
+        .0
+    }
+
+    pub fn stats(&self) -> EnemyStats {
+        match self {
+            EnemyKind::Gopher => EnemyStats {
+                name: "Gopher",
+                attack_interval: Duration::from_millis(2000),
+                damage: 3.,
+                health: 30.,
+                challenge: ChallengeStyle::Standard,
+            },
+            EnemyKind::Swarm => EnemyStats {
+                name: "Bat Swarm",
+                attack_interval: Duration::from_millis(700),
+                damage: 1.,
+                health: 15.,
+                challenge: ChallengeStyle::Short,
+            },
+            EnemyKind::Brute => EnemyStats {
+                name: "Beetle Brute",
+                attack_interval: Duration::from_millis(4000),
+                damage: 8.,
+                health: 60.,
+                challenge: ChallengeStyle::Long,
+            },
+            EnemyKind::Mirror => EnemyStats {
+                name: "Crystal Mirror",
+                attack_interval: Duration::from_millis(2000),
+                damage: 3.,
+                health: 30.,
+                challenge: ChallengeStyle::Punctuation,
+            },
+        }
+    }
+
+    // The texture of the enemy and which part of it to draw
+    fn sprite(&self) -> (Texture2D, Option<Rect>) {
+        match self {
+            EnemyKind::Gopher => (*GOPHER_TEXTURE, None),
+            EnemyKind::Swarm => (*CREATURES_TEXTURE, Some(Rect::new(64., 64., 16., 16.))),
+            EnemyKind::Brute => (*BEETLE_TEXTURE, None),
+            EnemyKind::Mirror => (*CREATURES_TEXTURE, Some(Rect::new(96., 80., 16., 16.))),
+        }
+    }
+}
+
+// The boss guarding the goal of a level, beaten by typing several sentences in a row
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Boss {
+    pub name: &'static str,
+    // Where the boss is on the creatures sheet
+    pub sprite: Rect,
+    pub phases: usize,
+    pub attack_interval: Duration,
+    pub damage: f32,
+    pub health: f32,
+    // The attack interval is multiplied by this after every phase
+    pub speed_up: f32,
+}
+
+impl Boss {
+    pub fn stats(&self, phase: usize) -> EnemyStats {
+        EnemyStats {
+            name: self.name,
+            attack_interval: self
+                .attack_interval
+                .mul_f32(self.speed_up.powi(phase as i32)),
+            damage: self.damage,
+            health: self.health,
+            challenge: ChallengeStyle::Standard,
+        }
+    }
+}
+
+// Whatever the player is fighting
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Foe {
+    Enemy(EnemyKind),
+    Boss { boss: Boss, phase: usize },
+}
+
+impl Foe {
+    pub fn stats(&self) -> EnemyStats {
+        match self {
+            Foe::Enemy(kind) => kind.stats(),
+            Foe::Boss { boss, phase } => boss.stats(*phase),
+        }
+    }
+
+    fn sprite(&self) -> (Texture2D, Option<Rect>) {
+        match self {
+            Foe::Enemy(kind) => kind.sprite(),
+            Foe::Boss { boss, .. } => (*CREATURES_TEXTURE, Some(boss.sprite)),
+        }
+    }
+}
+
+// The size of the enemy when drawn at the given width
+pub fn enemy_size(foe: Foe, width: f32) -> (f32, f32) {
+    let (texture, source) = foe.sprite();
+    let (source_width, source_height) = match source {
+        Some(rect) => (rect.w, rect.h),
+        None => (texture.width(), texture.height()),
+    };
+    (width, source_height * width / source_width)
+}
+
+pub fn draw_enemy(foe: Foe, x: f32, y: f32, width: f32, flip_x: bool) {
+    let (texture, source) = foe.sprite();
+    let (width, height) = enemy_size(foe, width);
+    draw_texture_ex(
+        texture,
+        x,
+        y,
+        WHITE,
+        DrawTextureParams {
+            dest_size: Some(Vec2::from([width, height])),
+            source,
+            flip_x,
+            ..Default::default()
+        },
+    );
+}
+
+pub fn draw_health_bar(x: f32, y: f32, width: f32, health: f32, max_health: f32, color: Color) {
+    let height = 20.;
+    draw_rectangle(x, y, width, height, Color::from_rgba(40, 40, 40, 255));
+    draw_rectangle(
+        x,
+        y,
+        width * (health / max_health).clamp(0., 1.),
+        height,
+        color,
+    );
+    draw_rectangle_lines(x, y, width, height, 3., BLACK);
+}
+
+// How much of the boss's health bar is left, every phase is an equal share of it
+pub fn boss_health_fraction(boss: &Boss, phase: usize, enemy_health: f32) -> f32 {
+    ((boss.phases - phase - 1) as f32 + enemy_health / boss.health) / boss.phases as f32
+}
+
+// A thicker bar split into one section per phase
+pub fn draw_boss_health_bar(x: f32, y: f32, width: f32, fraction: f32, boss: &Boss) {
+    let height = 36.;
+    draw_rectangle(x, y, width, height, Color::from_rgba(40, 0, 50, 255));
+    draw_rectangle(
+        x,
+        y,
+        width * fraction.clamp(0., 1.),
+        height,
+        Color::from_rgba(150, 40, 200, 255),
+    );
+    for phase in 1..boss.phases {
+        let phase_x = x + width * phase as f32 / boss.phases as f32;
+        draw_line(phase_x, y, phase_x, y + height, 3., BLACK);
+    }
+    draw_rectangle_lines(x, y, width, height, 5., GOLD);
+}
@@ -0,0 +1,334 @@
+use crate::map::{MapLayout, Node};
+use ::rand::{seq::SliceRandom, Rng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::{HashSet, VecDeque};
+
+// Lays out the nodes of a level and connects them, the player, goal, enemies and treasure
+// are placed afterwards. Every random choice has to come from rng so seeds can be replayed
+pub trait MapGenerator {
+    fn generate(&self, layout: &mut MapLayout, rng: &mut ChaCha8Rng) -> Vec<Node>;
+}
+
+fn distance(node_1: &Node, node_2: &Node) -> f32 {
+    (((node_1.x - node_2.x).pow(2) + (node_1.y - node_2.y).pow(2)) as f32).sqrt()
+}
+
+fn add_edge(nodes: &mut [Node], node_1: usize, node_2: usize) {
+    if node_1 != node_2 && !nodes[node_1].neighbors.contains(&node_2) {
+        nodes[node_1].neighbors.push(node_2);
+        nodes[node_2].neighbors.push(node_1);
+    }
+}
+
+fn remove_edge(nodes: &mut [Node], node_1: usize, node_2: usize) {
+    nodes[node_1]
+        .neighbors
+        .retain(|neighbor| *neighbor != node_2);
+    nodes[node_2]
+        .neighbors
+        .retain(|neighbor| *neighbor != node_1);
+}
+
+// Whether the edges cross somewhere other than a node they share
+fn edges_cross(nodes: &[Node], (a, b): (usize, usize), (c, d): (usize, usize)) -> bool {
+    if a == c || a == d || b == c || b == d {
+        return false;
+    }
+    let side = |p: &Node, q: &Node, r: &Node| {
+        ((q.x - p.x) * (r.y - p.y) - (q.y - p.y) * (r.x - p.x)).signum()
+    };
+    let (a, b, c, d) = (&nodes[a], &nodes[b], &nodes[c], &nodes[d]);
+    side(a, b, c) * side(a, b, d) < 0 && side(c, d, a) * side(c, d, b) < 0
+}
+
+fn edges(nodes: &[Node]) -> Vec<(usize, usize)> {
+    nodes
+        .iter()
+        .flat_map(|node| {
+            node.neighbors
+                .iter()
+                .filter(move |neighbor| node.index < **neighbor)
+                .map(move |neighbor| (node.index, *neighbor))
+        })
+        .collect()
+}
+
+// Which group of connected nodes each node is in
+pub fn components(nodes: &[Node]) -> Vec<usize> {
+    let mut component = vec![usize::MAX; nodes.len()];
+    for start in 0..nodes.len() {
+        if component[start] != usize::MAX {
+            continue;
+        }
+        component[start] = start;
+        let mut nodes_to_visit = VecDeque::from([start]);
+        while let Some(node) = nodes_to_visit.pop_front() {
+            for neighbor in &nodes[node].neighbors {
+                if component[*neighbor] == usize::MAX {
+                    component[*neighbor] = start;
+                    nodes_to_visit.push_back(*neighbor);
+                }
+            }
+        }
+    }
+    component
+}
+
+fn is_connected(nodes: &[Node]) -> bool {
+    components(nodes).iter().all(|component| *component == 0)
+}
+
+// Joins any separate groups of nodes with the shortest edges between them, so wherever the
+// player and goal end up there is a way between them
+pub fn connect_components(nodes: &mut [Node]) {
+    loop {
+        let component = components(nodes);
+        let mut closest: Option<(f32, usize, usize)> = None;
+        for inside in (0..nodes.len()).filter(|node| component[*node] == 0) {
+            for outside in (0..nodes.len()).filter(|node| component[*node] != 0) {
+                let length = distance(&nodes[inside], &nodes[outside]);
+                if closest.is_none_or(|(shortest, _, _)| length < shortest) {
+                    closest = Some((length, inside, outside));
+                }
+            }
+        }
+        match closest {
+            Some((_, inside, outside)) => add_edge(nodes, inside, outside),
+            None => return,
+        }
+    }
+}
+
+// Scatters as many nodes as the layout asks for over the grid
+fn scatter_nodes(layout: &MapLayout, rng: &mut ChaCha8Rng) -> Vec<Node> {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut locations: HashSet<(isize, isize)> = HashSet::new();
+    let grid_size = layout.grid_size;
+    while nodes.len() < layout.num_nodes {
+        let (x, y) = (
+            rng.gen_range(0..grid_size) as isize,
+            rng.gen_range(0..grid_size) as isize,
+        );
+        // Only add the nodes if it is a unique node
+        if locations.insert((x, y)) {
+            nodes.push(Node {
+                x,
+                y,
+                index: nodes.len(),
+                ..Default::default()
+            });
+        }
+    }
+    nodes
+}
+
+// Connects the nodes via a minimum spanning tree, grown from an arbitrary node
+fn spanning_tree(nodes: &mut [Node], rng: &mut ChaCha8Rng) {
+    let mut unconnected_nodes: Vec<usize> = (0..nodes.len()).collect();
+    let mut visited_nodes: Vec<usize> = match unconnected_nodes.pop() {
+        Some(index) => vec![index],
+        None => return,
+    };
+    while !unconnected_nodes.is_empty() {
+        // Allows for more natural linking between nodes
+        visited_nodes.shuffle(rng);
+        let mut closest: Option<(f32, usize, usize)> = None;
+        for visited_node in &visited_nodes {
+            for unconnected_node in &unconnected_nodes {
+                let length = distance(&nodes[*visited_node], &nodes[*unconnected_node]);
+                if closest.is_none_or(|(shortest, _, _)| length < shortest) {
+                    closest = Some((length, *visited_node, *unconnected_node));
+                }
+            }
+        }
+        let Some((_, visited_node, unconnected_node)) = closest else {
+            return;
+        };
+        add_edge(nodes, visited_node, unconnected_node);
+        visited_nodes.push(unconnected_node);
+        unconnected_nodes.retain(|index| *index != unconnected_node);
+    }
+}
+
+// Only one route between any two nodes
+pub struct SpanningTree;
+
+impl MapGenerator for SpanningTree {
+    fn generate(&self, layout: &mut MapLayout, rng: &mut ChaCha8Rng) -> Vec<Node> {
+        let mut nodes = scatter_nodes(layout, rng);
+        spanning_tree(&mut nodes, rng);
+        nodes
+    }
+}
+
+// A spanning tree with some of the shortest missing edges added back, making loops the player
+// can choose between
+pub struct LoopedTree {
+    // Extra edges to add for each node
+    pub extra_edges: f32,
+}
+
+impl MapGenerator for LoopedTree {
+    fn generate(&self, layout: &mut MapLayout, rng: &mut ChaCha8Rng) -> Vec<Node> {
+        let mut nodes = scatter_nodes(layout, rng);
+        spanning_tree(&mut nodes, rng);
+        let mut candidates: Vec<(usize, usize)> = (0..nodes.len())
+            .flat_map(|node_1| (node_1 + 1..nodes.len()).map(move |node_2| (node_1, node_2)))
+            .filter(|(node_1, node_2)| !nodes[*node_1].neighbors.contains(node_2))
+            .collect();
+        candidates.shuffle(rng);
+        candidates.sort_by(|(a, b), (c, d)| {
+            distance(&nodes[*a], &nodes[*b]).total_cmp(&distance(&nodes[*c], &nodes[*d]))
+        });
+        let mut num_extra_edges = (nodes.len() as f32 * self.extra_edges).round() as usize;
+        for candidate in candidates {
+            if num_extra_edges == 0 {
+                break;
+            }
+            // Edges that cross would look like paths that don't exist
+            if edges(&nodes)
+                .iter()
+                .all(|edge| !edges_cross(&nodes, candidate, *edge))
+            {
+                add_edge(&mut nodes, candidate.0, candidate.1);
+                num_extra_edges -= 1;
+            }
+        }
+        nodes
+    }
+}
+
+// Joins every pair of nodes that have no other node inside the circle between them (their
+// Gabriel graph), then removes some of those edges at random as long as everything stays connected
+pub struct Gabriel {
+    // The chance of each edge being kept
+    pub keep: f64,
+}
+
+impl MapGenerator for Gabriel {
+    fn generate(&self, layout: &mut MapLayout, rng: &mut ChaCha8Rng) -> Vec<Node> {
+        let mut nodes = scatter_nodes(layout, rng);
+        let squared = |a: &Node, b: &Node| (a.x - b.x).pow(2) + (a.y - b.y).pow(2);
+        for node_1 in 0..nodes.len() {
+            for node_2 in node_1 + 1..nodes.len() {
+                let (a, b) = (&nodes[node_1], &nodes[node_2]);
+                if nodes
+                    .iter()
+                    .filter(|c| c.index != node_1 && c.index != node_2)
+                    .all(|c| squared(a, c) + squared(b, c) >= squared(a, b))
+                {
+                    add_edge(&mut nodes, node_1, node_2);
+                }
+            }
+        }
+        let mut all_edges = edges(&nodes);
+        all_edges.shuffle(rng);
+        for (node_1, node_2) in all_edges {
+            if rng.gen_bool(self.keep) {
+                continue;
+            }
+            remove_edge(&mut nodes, node_1, node_2);
+            if !is_connected(&nodes) {
+                add_edge(&mut nodes, node_1, node_2);
+            }
+        }
+        nodes
+    }
+}
+
+// A winding corridor from one side of the map to the other with dead ends branching off it
+pub struct Corridors {
+    // The longest a branch can be
+    pub max_branch_length: usize,
+}
+
+impl MapGenerator for Corridors {
+    fn generate(&self, layout: &mut MapLayout, rng: &mut ChaCha8Rng) -> Vec<Node> {
+        let grid_size = layout.grid_size as isize;
+        let mut nodes: Vec<Node> = Vec::new();
+        let in_bounds =
+            |(x, y): (isize, isize)| (0..grid_size).contains(&x) && (0..grid_size).contains(&y);
+        let add_cell = |nodes: &mut Vec<Node>, cell: (isize, isize), from: Option<usize>| {
+            let index = nodes.len();
+            nodes.push(Node {
+                x: cell.0,
+                y: cell.1,
+                index,
+                ..Default::default()
+            });
+            if let Some(from) = from {
+                add_edge(nodes, from, index);
+            }
+            index
+        };
+
+        // The main corridor heads right, winding up and down on the way
+        let mut cell = (0, rng.gen_range(0..grid_size));
+        let mut current = add_cell(&mut nodes, cell, None);
+        let mut used: HashSet<(isize, isize)> = HashSet::from([cell]);
+        while cell.0 < grid_size - 1 {
+            let moves: Vec<(isize, isize)> = [(1, 0), (1, 0), (0, 1), (0, -1)]
+                .into_iter()
+                .map(|(x, y)| (cell.0 + x, cell.1 + y))
+                .filter(|next| in_bounds(*next) && !used.contains(next))
+                .collect();
+            let Some(next) = moves.choose(rng) else {
+                break;
+            };
+            cell = *next;
+            used.insert(cell);
+            current = add_cell(&mut nodes, cell, Some(current));
+        }
+
+        // Branches grow from anywhere until the map is big enough or there is no room left
+        let mut attempts = 0;
+        while nodes.len() < layout.num_nodes && attempts < 100 {
+            attempts += 1;
+            let mut branch = rng.gen_range(0..nodes.len());
+            let mut cell = (nodes[branch].x, nodes[branch].y);
+            let (x, y) = *[(1, 0), (-1, 0), (0, 1), (0, -1)].choose(rng).unwrap();
+            for _ in 0..rng.gen_range(1..=self.max_branch_length) {
+                let next = (cell.0 + x, cell.1 + y);
+                if !in_bounds(next) || used.contains(&next) || nodes.len() >= layout.num_nodes {
+                    break;
+                }
+                cell = next;
+                used.insert(cell);
+                branch = add_cell(&mut nodes, cell, Some(branch));
+            }
+        }
+        nodes
+    }
+}
+
+// A layout made by hand, nodes are given by their position on the grid and edges by the
+// positions of the nodes in that list
+pub struct Handmade {
+    pub grid_size: usize,
+    pub positions: Vec<(isize, isize)>,
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl MapGenerator for Handmade {
+    fn generate(&self, layout: &mut MapLayout, _rng: &mut ChaCha8Rng) -> Vec<Node> {
+        layout.grid_size = self.grid_size;
+        layout.num_nodes = self.positions.len();
+        let mut nodes: Vec<Node> = self
+            .positions
+            .iter()
+            .enumerate()
+            .map(|(index, (x, y))| Node {
+                x: *x,
+                y: *y,
+                index,
+                ..Default::default()
+            })
+            .collect();
+        for (node_1, node_2) in &self.edges {
+            if *node_1 < nodes.len() && *node_2 < nodes.len() {
+                add_edge(&mut nodes, *node_1, *node_2);
+            }
+        }
+        nodes
+    }
+}
@@ -0,0 +1,1144 @@
+// A windowless driver for the game logic, used to script whole runs in tests
+use crate::apply_card;
+use crate::config::{GameConfig, Settings};
+use crate::consumables::Consumable;
+use crate::editor;
+use crate::effects::Modifiers;
+use crate::end;
+use crate::enemy::{boss_health_fraction, EnemyKind, Foe};
+use crate::level::LevelFile;
+use crate::map::{Enemy, Graph, MapLayout, Tile};
+use crate::pause;
+use crate::platform::{Clock, Input, Renderer};
+use crate::profile::{record_run, Profile};
+use crate::relics::Relic;
+use crate::save::{autosave, game_from_save, save_contents, save_exists};
+use crate::shop::sentence_gold;
+use crate::start;
+use crate::treasure::{
+    card_by_title, draw_cards, offered_cards, reward_layout, Rarity, RewardPool,
+};
+use crate::{EndCondition, Game, GameState, RewardType};
+use ::rand::SeedableRng;
+use macroquad::prelude::KeyCode;
+use rand_chacha::ChaCha8Rng;
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const SCREEN_SIZE: (f32, f32) = (1920., 1080.);
+const FRAME_TIME: Duration = Duration::from_millis(16);
+
+#[derive(Default)]
+pub struct ScriptedInput {
+    chars: VecDeque<char>,
+    pressed: Vec<KeyCode>,
+    down: Vec<KeyCode>,
+    released: Vec<KeyCode>,
+    click: Option<(f32, f32)>,
+    release: Option<(f32, f32)>,
+}
+
+impl ScriptedInput {
+    // Clears everything that only lasts for a single frame
+    fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.click = None;
+        self.release = None;
+    }
+}
+
+impl Input for ScriptedInput {
+    fn char_pressed(&mut self) -> Option<char> {
+        self.chars.pop_front()
+    }
+
+    fn key_pressed(&self, key: KeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    fn key_down(&self, key: KeyCode) -> bool {
+        self.down.contains(&key)
+    }
+
+    fn key_released(&self, key: KeyCode) -> bool {
+        self.released.contains(&key)
+    }
+
+    fn mouse_clicked(&self) -> Option<(f32, f32)> {
+        self.click
+    }
+
+    fn mouse_released(&self) -> Option<(f32, f32)> {
+        self.release
+    }
+
+    fn screen_size(&self) -> (f32, f32) {
+        SCREEN_SIZE
+    }
+}
+
+pub struct SimulatedClock {
+    now: Instant,
+}
+
+impl SimulatedClock {
+    fn advance(&mut self, duration: Duration) {
+        self.now += duration;
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> Instant {
+        self.now
+    }
+}
+
+// Records every state that was drawn instead of drawing it
+#[derive(Default)]
+pub struct RecordingRenderer {
+    states: Vec<GameState>,
+}
+
+impl Renderer for RecordingRenderer {
+    fn draw(&mut self, game: &Game, _now: Instant) {
+        if self.states.last() != Some(&game.state) {
+            self.states.push(game.state);
+        }
+    }
+}
+
+pub struct Harness {
+    pub game: Game,
+    // Made for this harness and removed with it
+    data_dir: PathBuf,
+    pub input: ScriptedInput,
+    pub clock: SimulatedClock,
+    pub renderer: RecordingRenderer,
+}
+
+impl Harness {
+    pub fn new(seed: u64) -> Harness {
+        let mut game = Game::new(Graph::from_seed(seed, MapLayout::default()));
+        game.state = GameState::MainMap;
+        // Every harness gets its own empty directory so the player's files are never touched
+        static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+        let data_dir = std::env::temp_dir().join(format!(
+            "dungeon-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&data_dir).unwrap();
+        game.data_dir = data_dir.clone();
+        Harness {
+            game,
+            data_dir,
+            input: ScriptedInput::default(),
+            clock: SimulatedClock {
+                now: Instant::now(),
+            },
+            renderer: RecordingRenderer::default(),
+        }
+    }
+
+    pub fn step(&mut self) {
+        self.game.update(&mut self.input, &self.clock);
+        self.renderer.draw(&self.game, self.clock.now());
+        self.input.end_frame();
+        self.clock.advance(FRAME_TIME);
+    }
+
+    pub fn wait(&mut self, duration: Duration) {
+        let end = self.clock.now() + duration;
+        while self.clock.now() < end {
+            self.step();
+        }
+    }
+
+    // Steps until the condition holds, returning false if it never does
+    pub fn run_until(&mut self, condition: impl Fn(&Game) -> bool, max_frames: usize) -> bool {
+        for _ in 0..max_frames {
+            if condition(&self.game) {
+                return true;
+            }
+            self.step();
+        }
+        condition(&self.game)
+    }
+
+    pub fn press(&mut self, key: KeyCode) {
+        self.input.pressed.push(key);
+    }
+
+    pub fn click(&mut self, position: (f32, f32)) {
+        self.input.click = Some(position);
+    }
+
+    // Holds the mouse down at one position and lets go at another a frame later
+    pub fn drag(&mut self, from: (f32, f32), to: (f32, f32)) {
+        self.click(from);
+        self.step();
+        self.input.release = Some(to);
+        self.step();
+    }
+
+    pub fn click_node(&mut self, index: usize) {
+        self.click(self.game.graph.node_position(index, SCREEN_SIZE));
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        self.input.chars.extend(text.chars());
+    }
+
+    // The sentence the player has to type in the current combat
+    pub fn sentence(&self) -> String {
+        self.game
+            .variables
+            .sentence
+            .as_ref()
+            .unwrap()
+            .iter()
+            .collect()
+    }
+
+    // Types the sentence one character a frame until the fight or boss phase is over
+    pub fn type_sentence(&mut self) -> usize {
+        let sentence = self.game.variables.sentence.clone();
+        let mut typed = 0;
+        for c in self.sentence().chars() {
+            // The next sentence can be the same as the last, so also watch for it being cleared
+            if self.game.state != GameState::Combat
+                || self.game.variables.sentence != sentence
+                || self.game.player.sentence.len() != typed
+            {
+                break;
+            }
+            self.input.chars.push_back(c);
+            self.step();
+            typed += 1;
+        }
+        typed
+    }
+
+    // Saves the run the way quitting does and continues it from the title screen
+    pub fn save_and_continue(&mut self) {
+        autosave(&self.game);
+        self.game.state = GameState::Title;
+        self.game.save_found = save_exists(&self.game.data_dir);
+        self.game.variables.selected_title_option = 1;
+        self.press(KeyCode::Enter);
+        self.step();
+    }
+
+    pub fn choose_card(&mut self, card: usize) {
+        // The cards are only drawn once the reward screen has been shown
+        if self.game.variables.reward_offer.is_empty() {
+            self.step();
+        }
+        let cards = offered_cards(&self.game.variables.reward_offer);
+        let (_, (x, y)) = reward_layout(&cards, SCREEN_SIZE)[card];
+        self.click((x + 1., y + 1.));
+    }
+
+    // Plays whatever is in front of the player the way a perfect typist would
+    pub fn autoplay_step(&mut self) {
+        match self.game.state {
+            GameState::Combat => {
+                let position = self.game.player.sentence.len();
+                if let Some(c) = self.sentence().chars().nth(position) {
+                    self.input.chars.push_back(c);
+                }
+            }
+            GameState::CombatSummary => self.press(KeyCode::Enter),
+            GameState::Rewarded(_) => self.choose_card(0),
+            GameState::Shop => self.press(KeyCode::Enter),
+            _ => (),
+        }
+        self.step();
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+fn enemy_positions(game: &Game) -> Vec<usize> {
+    game.graph
+        .nodes
+        .iter()
+        .filter(|node| matches!(node.value, Tile::Enemy(_)))
+        .map(|node| node.index)
+        .collect()
+}
+
+fn fight_first_enemy(harness: &mut Harness) -> usize {
+    harness.click_node(enemy_positions(&harness.game)[0]);
+    harness.step();
+    // Any treasure on the way is picked up as normal and shops are left straight away
+    for _ in 0..2000 {
+        match harness.game.state {
+            GameState::Combat => break,
+            GameState::Rewarded(_) => harness.choose_card(0),
+            GameState::Shop => harness.press(KeyCode::Enter),
+            _ => (),
+        }
+        harness.step();
+    }
+    assert_eq!(harness.game.state, GameState::Combat);
+    // Another enemy on the way is fought first
+    harness.game.graph.current_player_position.unwrap()
+}
+
+#[test]
+fn the_title_menu_skips_continue_without_a_save() {
+    let mut harness = Harness::new(12);
+    harness.game.state = GameState::Title;
+    harness.press(KeyCode::Down);
+    harness.step();
+    assert_eq!(harness.game.variables.selected_title_option, 2);
+    harness.press(KeyCode::Enter);
+    harness.step();
+    assert_eq!(harness.game.state, GameState::Settings);
+
+    harness.press(KeyCode::Escape);
+    harness.step();
+    // Going up from the top wraps around to quit
+    harness.game.variables.selected_title_option = 0;
+    harness.press(KeyCode::Up);
+    harness.step();
+    harness.press(KeyCode::Enter);
+    harness.step();
+    assert_eq!(harness.game.state, GameState::Quit);
+}
+
+#[test]
+fn settings_and_finished_runs_are_kept_on_disk() {
+    let mut harness = Harness::new(12);
+    let data_dir = harness.game.data_dir.clone();
+    let mut settings = Settings::load(&data_dir);
+    assert!(!settings.word_travel);
+    settings.word_travel = true;
+    settings.save(&data_dir);
+    assert!(Settings::load(&data_dir).word_travel);
+
+    assert!(Profile::load(&data_dir).runs.is_empty());
+    record_run(&mut harness.game, EndCondition::Success);
+    let runs = Profile::load(&data_dir).runs;
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].seed, harness.game.graph.seed);
+}
+
+#[test]
+fn practice_mode_has_no_enemies() {
+    let mut harness = Harness::new(13);
+    harness.game.state = GameState::Title;
+    let (x, y) = start::title_menu_position(3, SCREEN_SIZE);
+    harness.click((x, y - 10.));
+    harness.step();
+    assert_eq!(harness.game.state, GameState::Practice);
+    harness.step();
+    let sentence = harness.sentence();
+    harness.type_text(&sentence);
+    assert!(harness.run_until(|game| game.variables.practice_stats.is_some(), 1000));
+    assert_eq!(harness.game.player.health, harness.game.player.max_health);
+    harness.press(KeyCode::Escape);
+    harness.step();
+    assert!(harness.game.pause.is_some());
+    let (x, y) = pause::pause_menu_position(3, SCREEN_SIZE);
+    harness.click((x, y - 10.));
+    harness.step();
+    assert_eq!(harness.game.state, GameState::Title);
+    assert!(harness.game.variables.practice_stats.is_none());
+}
+
+#[test]
+fn walking_onto_an_enemy_starts_combat() {
+    let mut harness = Harness::new(1);
+    fight_first_enemy(&mut harness);
+    assert!(harness.renderer.states.contains(&GameState::EnterCombat));
+    assert!(harness.game.variables.sentence.is_some());
+}
+
+#[test]
+fn the_map_can_be_walked_with_the_keyboard() {
+    let mut harness = Harness::new(1);
+    let enemy = enemy_positions(&harness.game)[0];
+    let labels = harness.game.graph.node_labels();
+    let (_, label) = labels.iter().find(|(node, _)| *node == enemy).unwrap();
+    harness.type_text(label);
+    harness.step();
+    assert_eq!(harness.game.variables.map_cursor.node, Some(enemy));
+    harness.press(KeyCode::Enter);
+    harness.step();
+    assert!(harness.game.variables.map_cursor.node.is_none());
+    for _ in 0..2000 {
+        match harness.game.state {
+            GameState::Combat => break,
+            // Cards can be chosen with the number keys
+            GameState::Rewarded(_) => harness.press(KeyCode::Key2),
+            _ => (),
+        }
+        harness.step();
+    }
+    // Any enemy on the way stops the player too
+    assert_eq!(harness.game.state, GameState::Combat);
+    assert!(harness.game.graph.current_enemy().is_some());
+}
+
+#[test]
+fn arrow_keys_move_the_map_cursor() {
+    let mut harness = Harness::new(3);
+    let graph = &harness.game.graph;
+    let start = &graph.nodes[graph.current_player_position.unwrap()];
+    let (x, has_right) = (
+        start.x,
+        graph
+            .reachable_nodes()
+            .iter()
+            .any(|node| graph.nodes[*node].x > start.x),
+    );
+    harness.press(if has_right {
+        KeyCode::Right
+    } else {
+        KeyCode::Left
+    });
+    harness.step();
+    let cursor = harness.game.variables.map_cursor.node.unwrap();
+    let cursor_x = harness.game.graph.nodes[cursor].x;
+    assert!(if has_right {
+        cursor_x > x
+    } else {
+        cursor_x < x
+    });
+    harness.press(KeyCode::Enter);
+    harness.step();
+    assert_eq!(harness.game.graph.player_path.first(), Some(&cursor));
+}
+
+#[test]
+fn typing_a_nodes_word_travels_there() {
+    let mut harness = Harness::new(7);
+    harness.game.settings.word_travel = true;
+    harness.step();
+    let words = harness.game.variables.map_cursor.words.clone();
+    let start = harness.game.graph.current_player_position.unwrap();
+    assert_eq!(words.len(), harness.game.graph.nodes[start].neighbors.len());
+
+    // A word that can't be any of them is a mistake, however much of it is wrong
+    let health = harness.game.player.health;
+    harness.type_text("999");
+    harness.step();
+    assert_eq!(harness.game.player.health, health - 1.);
+    harness.type_text(" 99 ");
+    harness.step();
+    assert_eq!(harness.game.player.health, health - 2.);
+    assert!(harness.game.graph.player_path.is_empty());
+
+    let (node, word) = &words[0];
+    harness.type_text(word);
+    assert!(harness.run_until(
+        |game| game.graph.current_player_position == Some(*node),
+        1000
+    ));
+    assert_eq!(harness.game.player.health, health - 2.);
+}
+
+#[test]
+fn mistyped_travel_words_can_kill_the_player() {
+    let mut harness = Harness::new(7);
+    harness.game.settings.word_travel = true;
+    harness.game.player.health = 1.;
+    harness.step();
+    harness.type_text("999");
+    assert!(harness.run_until(
+        |game| game.state == GameState::EndOfGame(EndCondition::Death),
+        10
+    ));
+    record_run(&mut harness.game, EndCondition::Death);
+    let cause = harness.game.profile.runs[0].cause_of_death.clone().unwrap();
+    assert!(cause.starts_with("Lost their way"));
+}
+
+#[test]
+fn typing_the_sentence_defeats_the_enemy() {
+    let mut harness = Harness::new(2);
+    let enemy = fight_first_enemy(&mut harness);
+    harness.type_sentence();
+    assert!(harness.run_until(|game| game.state == GameState::CombatSummary, 1000));
+    harness.press(KeyCode::Enter);
+    assert!(harness.run_until(|game| game.state == GameState::MainMap, 1000));
+    assert_eq!(harness.game.variables.num_enemies_defeated, 1);
+    assert_eq!(harness.game.graph.nodes[enemy].value, Tile::Empty);
+}
+
+#[test]
+fn continuing_after_a_won_fight_doesnt_pay_out_again() {
+    let mut harness = Harness::new(2);
+    let enemy = fight_first_enemy(&mut harness);
+    // Quitting mid-fight restarts it
+    let saved = save_contents(&harness.game).unwrap();
+    assert_eq!(
+        game_from_save(&saved).unwrap().state,
+        GameState::EnterCombat
+    );
+
+    harness.type_sentence();
+    assert!(harness.run_until(|game| game.state == GameState::CombatSummary, 1000));
+    let gold = harness.game.variables.gold;
+    let stats = harness.game.variables.combat_stats.len();
+    harness.save_and_continue();
+    assert_eq!(harness.game.state, GameState::ExitCombat);
+    assert!(harness.run_until(|game| game.state == GameState::MainMap, 1000));
+    assert_eq!(harness.game.graph.nodes[enemy].value, Tile::Empty);
+    assert_eq!(harness.game.variables.gold, gold);
+    assert_eq!(harness.game.variables.combat_stats.len(), stats);
+}
+
+#[test]
+fn fast_typing_finishes_the_fight_early() {
+    let mut harness = Harness::new(10);
+    fight_first_enemy(&mut harness);
+    let sentence = harness.sentence();
+    let health = harness.game.variables.enemy_health;
+    let typed = harness.type_sentence();
+    assert!(harness.game.variables.enemy_health < health);
+    // One character a frame is far faster than the reference speed
+    assert_eq!(harness.game.state, GameState::CombatSummary);
+    assert!(typed < sentence.len());
+}
+
+#[test]
+fn mistakes_break_the_streak() {
+    let mut harness = Harness::new(11);
+    fight_first_enemy(&mut harness);
+    harness.game.variables.enemy_health = f32::INFINITY;
+    let sentence = harness.sentence();
+    let first_word = &sentence[..=sentence.find(' ').unwrap()];
+    harness.type_text(first_word);
+    harness.wait(Duration::from_millis(16 * first_word.len() as u64));
+    assert_eq!(harness.game.variables.streak, 1);
+    harness.type_text("~");
+    harness.step();
+    assert_eq!(harness.game.variables.streak, 0);
+}
+
+#[test]
+fn combat_records_typing_stats() {
+    let mut harness = Harness::new(7);
+    fight_first_enemy(&mut harness);
+    // Keep the enemy standing until the whole sentence is typed
+    harness.game.variables.enemy_health = f32::INFINITY;
+    let sentence = harness.sentence();
+    let first = sentence.chars().next().unwrap();
+    // Make one mistake on the first character then fix it
+    harness.wait(Duration::from_millis(500));
+    harness.type_text("~");
+    harness.step();
+    harness.input.down.push(KeyCode::Backspace);
+    harness.step();
+    harness.input.down.clear();
+    harness.input.released.push(KeyCode::Backspace);
+    harness.type_text(&sentence);
+    assert!(harness.run_until(|game| game.state == GameState::CombatSummary, 1000));
+
+    let stats = &harness.game.variables.combat_stats[0];
+    assert_eq!(stats.backspaces, 1);
+    assert_eq!(stats.char_errors.get(&first), Some(&1));
+    // The first character has nothing before it to make a pair with
+    assert!(stats.bigram_errors.is_empty());
+    let num_keystrokes = sentence.len() as f32 + 1.;
+    assert_eq!(stats.raw_accuracy, sentence.len() as f32 / num_keystrokes);
+    assert_eq!(stats.corrected_accuracy, 1. - 1. / sentence.len() as f32);
+    assert!(stats.time_to_first_keystroke >= 0.5);
+    // One character a frame is 16ms a character, or 750 words per minute
+    assert!(stats.wpm > 600. && stats.wpm < 800.);
+}
+
+#[test]
+fn mistakes_can_be_deleted() {
+    let mut harness = Harness::new(3);
+    fight_first_enemy(&mut harness);
+    harness.type_text("#");
+    harness.step();
+    assert_eq!(harness.game.player.sentence, vec!['#']);
+    harness.input.down.push(KeyCode::Backspace);
+    harness.step();
+    assert!(harness.game.player.sentence.is_empty());
+}
+
+#[test]
+fn the_enemy_attacks_while_the_player_waits() {
+    let mut harness = Harness::new(4);
+    fight_first_enemy(&mut harness);
+    let enemy = harness.game.enemy_stats();
+    let health = harness.game.player.health;
+    harness.wait(enemy.attack_interval + Duration::from_millis(100));
+    assert_eq!(harness.game.player.health, health - enemy.damage);
+}
+
+#[test]
+fn pausing_freezes_the_enemy() {
+    let mut harness = Harness::new(4);
+    fight_first_enemy(&mut harness);
+    let enemy = harness.game.enemy_stats();
+    let health = harness.game.player.health;
+    harness.wait(enemy.attack_interval / 2);
+    harness.press(KeyCode::Escape);
+    harness.step();
+    assert!(harness.game.pause.is_some());
+    harness.type_text("abc");
+    harness.wait(enemy.attack_interval * 5);
+    assert_eq!(harness.game.player.health, health);
+    assert!(harness.game.player.sentence.is_empty());
+
+    harness.press(KeyCode::Escape);
+    harness.step();
+    assert!(harness.game.pause.is_none());
+    assert_eq!(harness.game.state, GameState::Combat);
+    // Only the time before the pause counts towards the next attack
+    harness.wait(enemy.attack_interval / 4);
+    assert_eq!(harness.game.player.health, health);
+    harness.wait(enemy.attack_interval / 2);
+    assert_eq!(harness.game.player.health, health - enemy.damage);
+}
+
+#[test]
+fn abandoning_a_run_goes_back_to_the_title() {
+    let mut harness = Harness::new(6);
+    autosave(&harness.game);
+    assert!(save_exists(&harness.game.data_dir));
+    harness.press(KeyCode::Escape);
+    harness.step();
+    harness.game.pause.as_mut().unwrap().selected = 3;
+    harness.press(KeyCode::Enter);
+    harness.step();
+    assert_eq!(harness.game.state, GameState::Title);
+    assert!(harness.game.pause.is_none());
+    assert!(!harness.game.save_found);
+}
+
+#[test]
+fn harder_difficulties_make_bigger_maps_and_faster_enemies() {
+    // Without the fog so the first enemy can be clicked on
+    let config = GameConfig {
+        fog_of_war: false,
+        ..GameConfig::hard()
+    };
+    let layout = MapLayout::new(&config);
+    assert!(layout.num_nodes > MapLayout::default().num_nodes);
+    let mut harness = Harness::new(4);
+    harness.game.graph = Graph::from_seed(4, layout);
+    harness.game.variables.config = config;
+    assert_eq!(harness.game.graph.nodes.len(), layout.num_nodes);
+    assert_eq!(enemy_positions(&harness.game).len(), layout.num_enemies);
+
+    fight_first_enemy(&mut harness);
+    let base = harness.game.foe().stats();
+    let enemy = harness.game.enemy_stats();
+    assert!(enemy.attack_interval < base.attack_interval);
+    let health = harness.game.player.health;
+    harness.wait(enemy.attack_interval + Duration::from_millis(100));
+    assert_eq!(harness.game.player.health, health - base.damage * 1.5);
+}
+
+#[test]
+fn mirrors_punish_mistyped_punctuation() {
+    let mut harness = Harness::new(8);
+    for index in enemy_positions(&harness.game) {
+        harness.game.graph.nodes[index].value = Tile::Enemy(Enemy {
+            kind: EnemyKind::Mirror,
+        });
+    }
+    fight_first_enemy(&mut harness);
+    harness.game.variables.enemy_health = f32::INFINITY;
+    let sentence: Vec<char> = harness.sentence().chars().collect();
+    let position = sentence
+        .iter()
+        .position(|c| c.is_ascii_punctuation())
+        .unwrap();
+    harness.type_text(&sentence[..position].iter().collect::<String>());
+    assert!(harness.run_until(|game| game.player.sentence.len() == position, 1000));
+
+    let health = harness.game.player.health;
+    harness.game.variables.last_attack = harness.clock.now();
+    harness.type_text(if sentence[position] == '.' { "," } else { "." });
+    harness.step();
+    assert_eq!(
+        harness.game.player.health,
+        health - EnemyKind::Mirror.stats().damage
+    );
+}
+
+#[test]
+fn standing_still_in_combat_kills_the_player() {
+    let mut harness = Harness::new(5);
+    fight_first_enemy(&mut harness);
+    assert!(harness.run_until(
+        |game| game.state == GameState::EndOfGame(EndCondition::Death),
+        10_000
+    ));
+    let foe = harness.game.foe().stats().name;
+    record_run(&mut harness.game, EndCondition::Death);
+    let cause = harness.game.profile.runs[0].cause_of_death.clone().unwrap();
+    assert!(cause.starts_with(&format!("Defeated by a {}", foe)));
+    harness.press(KeyCode::R);
+    harness.step();
+    assert_eq!(harness.game.state, GameState::LoadTextures);
+}
+
+#[test]
+fn gold_is_earned_in_combat_and_spent_in_the_shop() {
+    let mut harness = Harness::new(2);
+    fight_first_enemy(&mut harness);
+    harness.type_sentence();
+    assert!(harness.run_until(|game| game.state == GameState::CombatSummary, 1000));
+    assert!(harness.game.variables.gold > 0);
+    harness.press(KeyCode::Enter);
+    assert!(harness.run_until(|game| game.state == GameState::MainMap, 1000));
+
+    let shop = harness
+        .game
+        .graph
+        .nodes
+        .iter()
+        .position(|node| node.value == Tile::Shop)
+        .unwrap();
+    for node in &mut harness.game.graph.nodes {
+        if node.value != Tile::Shop {
+            node.value = Tile::Empty;
+        }
+    }
+    harness.game.graph.layout.fog_of_war = false;
+    harness.click_node(shop);
+    assert!(harness.run_until(|game| game.state == GameState::Shop, 5000));
+    assert_eq!(harness.game.graph.shop_stock.len(), 5);
+
+    harness.game.variables.gold = 30;
+    harness.game.player.health = 10.;
+    harness.press(KeyCode::Key1);
+    harness.step();
+    assert_eq!(harness.game.variables.gold, 5);
+    assert_eq!(harness.game.player.health, 35.);
+    assert_eq!(harness.game.graph.shop_stock.len(), 4);
+    // Nothing else can be afforded
+    harness.press(KeyCode::Key1);
+    harness.step();
+    assert_eq!(harness.game.graph.shop_stock.len(), 4);
+
+    harness.press(KeyCode::Enter);
+    harness.step();
+    assert_eq!(harness.game.state, GameState::MainMap);
+    let next = harness.game.graph.nodes[shop].neighbors[0];
+    harness.click_node(next);
+    assert!(harness.run_until(
+        |game| game.graph.current_player_position == Some(next),
+        1000
+    ));
+    assert_eq!(harness.game.graph.nodes[shop].value, Tile::Shop);
+}
+
+#[test]
+fn rewards_can_be_rerolled_or_skipped() {
+    let mut harness = Harness::new(5);
+    let position = harness.game.graph.current_player_position.unwrap();
+    harness.game.graph.nodes[position].value = Tile::Treasure;
+    harness.game.state = GameState::Rewarded(RewardType::Treasure);
+    harness.step();
+    let offer = harness.game.variables.reward_offer.clone();
+    assert_eq!(offer.len(), 3);
+    assert_eq!(offer.iter().collect::<HashSet<_>>().len(), 3);
+    // Chests never offer permanent cards
+    assert!(offered_cards(&offer).iter().all(|card| !card.permanent()));
+
+    // Rerolling needs gold
+    harness.press(KeyCode::R);
+    harness.step();
+    assert_eq!(harness.game.variables.reward_offer, offer);
+    harness.game.variables.gold = 15;
+    harness.press(KeyCode::R);
+    harness.step();
+    assert_eq!(harness.game.variables.gold, 5);
+    assert_eq!(harness.game.variables.reward_offer.len(), 3);
+    assert_eq!(
+        harness.game.state,
+        GameState::Rewarded(RewardType::Treasure)
+    );
+
+    let health = harness.game.player.health;
+    harness.press(KeyCode::S);
+    harness.step();
+    assert_eq!(harness.game.state, GameState::MainMap);
+    assert_eq!(harness.game.player.health, health);
+    assert!(harness.game.variables.reward_offer.is_empty());
+    assert_eq!(harness.game.graph.nodes[position].value, Tile::Empty);
+    assert!(harness.game.variables.gold > 5);
+}
+
+#[test]
+fn bosses_give_rarer_cards_than_chests() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let count_legendary = |pool: RewardPool, rng: &mut ChaCha8Rng| {
+        let pool = RewardPool { cards: 1, ..pool };
+        (0..200)
+            .flat_map(|_| offered_cards(&draw_cards(&pool, rng)))
+            .filter(|card| card.rarity == Rarity::Legendary)
+            .count()
+    };
+    let chest = count_legendary(RewardPool::CHEST, &mut rng);
+    for level in Graph::levels() {
+        let boss = count_legendary(level.boss_reward, &mut rng);
+        assert!(
+            chest < boss,
+            "{} legendary from chests, {} from the {}",
+            chest,
+            boss,
+            level.boss.name
+        );
+    }
+
+    // Each boss offers as many cards as its level says
+    let mut harness = Harness::new(9);
+    for level in 1..harness.game.graph.background_order.len() {
+        harness.game.variables.current_background = level;
+        harness.game.variables.reward_offer.clear();
+        harness.game.state = GameState::Rewarded(RewardType::EndOfLevel);
+        harness.step();
+        assert_eq!(
+            harness.game.variables.reward_offer.len(),
+            harness.game.graph.background_order[level - 1]
+                .boss_reward
+                .cards
+        );
+    }
+}
+
+#[test]
+fn card_effects_wear_off_after_the_next_brawl() {
+    let mut harness = Harness::new(6);
+    let card = |title| card_by_title(title).unwrap();
+    let (player, variables) = (&mut harness.game.player, &mut harness.game.variables);
+    player.health = 50.;
+    for title in ["Supplements", "Stronger Armor", "Bulked Up", "Crab Food"] {
+        apply_card(card(title), player, variables);
+    }
+    assert_eq!(player.health, 90.);
+    assert!((player.max_health - 110.).abs() < 0.001);
+    assert!(player.armoured);
+    let modifiers = variables.card_effects.current();
+    assert_eq!(modifiers.sentence_length(100), 90);
+    assert_eq!(modifiers.damage(5.), 4.);
+
+    variables.card_effects.end_brawl(player);
+    assert!(!player.armoured);
+    assert_eq!(variables.card_effects.current(), Modifiers::default());
+}
+
+#[test]
+fn lucky_pebbles_pay_for_every_word() {
+    let mut harness = Harness::new(2);
+    harness.game.variables.relics = vec![Relic::LuckyPebble];
+    fight_first_enemy(&mut harness);
+    let gold = harness.game.variables.gold;
+    harness.type_sentence();
+    assert!(harness.run_until(|game| game.state == GameState::CombatSummary, 1000));
+    let variables = &harness.game.variables;
+    let stats = variables.combat_stats.last().unwrap();
+    // A gold for every word on top of the gold for the sentence
+    assert!(variables.words_credited > 0);
+    assert_eq!(
+        variables.gold,
+        gold + sentence_gold(stats, false) + variables.words_credited
+    );
+}
+
+#[test]
+fn consumables_are_used_with_keys_or_commands() {
+    let mut harness = Harness::new(2);
+    fight_first_enemy(&mut harness);
+    let consumables = &mut harness.game.variables.consumables;
+    for consumable in [Consumable::Potion, Consumable::Potion, Consumable::SkipWord] {
+        assert!(consumables.add(consumable));
+    }
+    harness.game.player.health = 20.;
+    harness.press(KeyCode::F1);
+    harness.step();
+    assert!(harness.game.player.health > 40.);
+    assert_eq!(
+        harness.game.variables.consumables.count(Consumable::Potion),
+        1
+    );
+    // Pausing doesn't run the cooldown down
+    harness.press(KeyCode::Escape);
+    harness.step();
+    harness.wait(Consumable::Potion.cooldown() * 2);
+    harness.press(KeyCode::Escape);
+    harness.step();
+    harness.press(KeyCode::F1);
+    harness.step();
+    assert_eq!(
+        harness.game.variables.consumables.count(Consumable::Potion),
+        1
+    );
+
+    harness.type_text("/skip");
+    for _ in 0.."/skip".len() {
+        harness.step();
+    }
+    let sentence = harness.sentence();
+    let first_word = sentence.split_inclusive(' ').next().unwrap();
+    assert_eq!(
+        harness.game.player.sentence.iter().collect::<String>(),
+        first_word
+    );
+    assert_eq!(
+        harness
+            .game
+            .variables
+            .consumables
+            .count(Consumable::SkipWord),
+        0
+    );
+    assert_eq!(harness.game.variables.words_credited, 1);
+    // Typing the command isn't a mistake
+    assert_eq!(harness.game.variables.streak, 1);
+
+    // Until it stops being one, then every character of it is
+    harness.game.variables.relics.push(Relic::PatienceStone);
+    let last_attack = harness.game.variables.last_attack;
+    harness.type_text("/hx");
+    for _ in 0.."/hx".len() - 1 {
+        harness.step();
+    }
+    assert_eq!(harness.game.variables.streak, 1);
+    assert_eq!(harness.game.variables.last_attack, last_attack);
+    harness.step();
+    assert_eq!(harness.game.variables.streak, 0);
+    assert_eq!(
+        harness.game.variables.last_attack,
+        last_attack + Duration::from_millis(300) * 3
+    );
+}
+
+#[test]
+fn the_goal_is_guarded_by_a_boss() {
+    let mut harness = Harness::new(9);
+    // Clear the way so the only fight is the boss
+    for node in &mut harness.game.graph.nodes {
+        node.value = Tile::Empty;
+    }
+    harness.click_node(harness.game.graph.goal_position.unwrap());
+    assert!(harness.run_until(|game| game.state == GameState::Combat, 5000));
+    let boss = match harness.game.foe() {
+        Foe::Boss { boss, phase: 0 } => boss,
+        foe => panic!("Expected the first phase of a boss, found {:?}", foe),
+    };
+
+    let mut attack_interval = boss.stats(0).attack_interval;
+    for phase in 1..boss.phases {
+        harness.type_sentence();
+        assert!(harness.run_until(|game| game.variables.boss_phase == Some(phase), 1000));
+        assert_eq!(harness.game.state, GameState::Combat);
+        // Each phase attacks faster than the last
+        let stats = harness.game.foe().stats();
+        assert!(stats.attack_interval < attack_interval);
+        attack_interval = stats.attack_interval;
+    }
+    harness.type_sentence();
+    assert!(harness.run_until(|game| game.state == GameState::CombatSummary, 1000));
+    assert_eq!(harness.game.variables.combat_stats.len(), boss.phases);
+    harness.press(KeyCode::Enter);
+    assert!(harness.run_until(
+        |game| game.state == GameState::Rewarded(RewardType::EndOfLevel),
+        1000
+    ));
+    assert_eq!(harness.game.variables.current_background, 1);
+    assert_eq!(harness.game.variables.boss_phase, None);
+    // The walk to the goal explored some of the first level
+    let explored = harness.game.variables.explored[0];
+    assert!(explored > 0. && explored < 1.);
+}
+
+#[test]
+fn the_boss_health_bar_drops_a_share_each_phase() {
+    let mut harness = Harness::new(9);
+    for node in &mut harness.game.graph.nodes {
+        node.value = Tile::Empty;
+    }
+    harness.click_node(harness.game.graph.goal_position.unwrap());
+    assert!(harness.run_until(|game| game.state == GameState::Combat, 5000));
+    let boss = match harness.game.foe() {
+        Foe::Boss { boss, .. } => boss,
+        foe => panic!("Expected a boss, found {:?}", foe),
+    };
+    let share = 1. / boss.phases as f32;
+    for phase in 0..boss.phases {
+        assert_eq!(harness.game.variables.boss_phase, Some(phase));
+        let start = boss_health_fraction(&boss, phase, harness.game.variables.enemy_health);
+        assert!((start - (boss.phases - phase) as f32 * share).abs() < 0.001);
+        let end = boss_health_fraction(&boss, phase, 0.);
+        assert!((end - (boss.phases - phase - 1) as f32 * share).abs() < 0.001);
+        if phase + 1 < boss.phases {
+            harness.type_sentence();
+            assert!(harness.run_until(|game| game.variables.boss_phase == Some(phase + 1), 1000));
+        }
+    }
+}
+
+#[test]
+fn the_tutorial_level_can_be_played() {
+    let level = LevelFile::load("levels/tutorial.json").unwrap();
+    let mut harness = Harness::new(3);
+    harness.game.graph = Graph::from_level(3, level.clone());
+    let graph = &harness.game.graph;
+    assert_eq!(graph.current_player_position, Some(level.start));
+    assert_eq!(graph.goal_position, Some(level.goal));
+    assert_eq!(graph.background_order.len(), 1);
+    assert_eq!(graph.layout.num_enemies, 2);
+    assert_eq!(graph.nodes[2].value, Tile::Treasure);
+
+    let enemy = fight_first_enemy(&mut harness);
+    assert_eq!(
+        harness.game.graph.nodes[enemy].value,
+        Tile::Enemy(Enemy {
+            kind: EnemyKind::Gopher
+        })
+    );
+}
+
+#[test]
+fn a_level_built_in_the_editor_can_be_played() {
+    let mut harness = Harness::new(4);
+    harness.game.state = GameState::Editor;
+    let cell = |x, y| editor::cell_position((x, y), 6, SCREEN_SIZE);
+    for x in 0..4 {
+        harness.click(cell(x, 2));
+        harness.step();
+    }
+    assert_eq!(harness.game.editor.nodes.len(), 4);
+    // The last node placed is still selected
+    harness.press(KeyCode::G);
+    harness.step();
+    harness.click(cell(2, 2));
+    harness.step();
+    harness.press(KeyCode::Key3);
+    harness.step();
+    harness.drag(cell(0, 2), cell(1, 2));
+    harness.drag(cell(1, 2), cell(3, 2));
+    assert_eq!(harness.game.editor.edges, vec![(0, 1), (1, 3)]);
+
+    // Without a start it can't be played
+    harness.press(KeyCode::P);
+    harness.step();
+    assert_eq!(harness.game.state, GameState::Editor);
+    harness.click(cell(0, 2));
+    harness.step();
+    harness.press(KeyCode::S);
+    harness.step();
+
+    // Removing the second node splits the start from the goal
+    harness.click(cell(1, 2));
+    harness.step();
+    harness.press(KeyCode::Delete);
+    harness.step();
+    assert_eq!(harness.game.editor.start, Some(0));
+    assert_eq!(harness.game.editor.goal, Some(2));
+    harness.press(KeyCode::V);
+    harness.step();
+    assert!(harness.game.editor.route.is_empty());
+
+    harness.drag(cell(0, 2), cell(2, 2));
+    harness.drag(cell(2, 2), cell(3, 2));
+    harness.press(KeyCode::V);
+    harness.step();
+    assert_eq!(harness.game.editor.route, vec![0, 1, 2]);
+    harness.press(KeyCode::P);
+    harness.step();
+    assert!(harness.game.state.in_run());
+    let graph = &harness.game.graph;
+    assert_eq!(graph.nodes.len(), 3);
+    assert_eq!(graph.current_player_position, Some(0));
+    assert_eq!(
+        graph.nodes[1].value,
+        Tile::Enemy(Enemy {
+            kind: EnemyKind::Brute
+        })
+    );
+}
+
+#[test]
+fn fog_of_war_hides_nodes_until_they_are_approached() {
+    let mut harness = Harness::new(10);
+    let config = GameConfig {
+        fog_of_war: true,
+        ..GameConfig::default()
+    };
+    harness.game.graph = Graph::from_seed(10, MapLayout::new(&config));
+    let graph = &harness.game.graph;
+    let start = graph.current_player_position.unwrap();
+    let hidden: Vec<usize> = (0..graph.nodes.len())
+        .filter(|node| !graph.revealed(*node))
+        .collect();
+    assert!(!hidden.is_empty());
+    for node in &graph.nodes[start].neighbors {
+        assert!(graph.revealed(*node));
+    }
+    assert!(graph.revealed(graph.goal_position.unwrap()));
+    assert!(graph
+        .node_labels()
+        .iter()
+        .all(|(node, _)| !hidden.contains(node)));
+
+    // Hidden nodes can't be clicked on
+    harness.click_node(hidden[0]);
+    harness.step();
+    assert!(harness.game.graph.player_path.is_empty());
+
+    // Walking somewhere reveals what is around it
+    let next = harness.game.graph.nodes[start].neighbors[0];
+    harness.click_node(next);
+    assert!(harness.run_until(
+        |game| game.graph.current_player_position != Some(start),
+        1000
+    ));
+    let graph = &harness.game.graph;
+    for node in &graph.nodes[next].neighbors {
+        assert!(graph.revealed(*node));
+    }
+    assert_eq!(graph.explored(), 2. / graph.nodes.len() as f32);
+}
+
+#[test]
+fn reaching_the_final_goal_wins_the_run() {
+    let mut harness = Harness::new(6);
+    for _ in 0..50_000 {
+        if let GameState::EndOfGame(_) = harness.game.state {
+            break;
+        }
+        if harness.game.state == GameState::MainMap && harness.game.graph.player_path.is_empty() {
+            harness.click_node(harness.game.graph.goal_position.unwrap());
+        }
+        harness.autoplay_step();
+    }
+    assert_eq!(
+        harness.game.state,
+        GameState::EndOfGame(EndCondition::Success)
+    );
+    assert!(harness
+        .renderer
+        .states
+        .contains(&GameState::Rewarded(RewardType::EndOfLevel)));
+    let variables = &harness.game.variables;
+    assert_eq!(
+        variables.explored.len(),
+        harness.game.graph.background_order.len()
+    );
+    assert!(
+        end::run_score(variables.num_enemies_defeated, &variables.explored)
+            > 1000 * variables.explored.len()
+    );
+}
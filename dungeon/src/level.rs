@@ -0,0 +1,221 @@
+use crate::enemy::EnemyKind;
+use crate::generators::{components, Handmade};
+use crate::map::{Background, Enemy, Graph, LevelInfo, MapLayout, Node, Tile};
+use crate::shop::shop_stock;
+use ::rand::SeedableRng;
+use macroquad::prelude::Color;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+
+// A level made by hand and loaded from a json file, the format is described in the README
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct LevelFile {
+    pub name: String,
+    pub background: Background,
+    // Red, green and blue from 0 to 255
+    pub ground_color: [u8; 3],
+    pub sky_color: [u8; 3],
+    pub grid_size: usize,
+    pub nodes: Vec<LevelNode>,
+    // Pairs of indices into nodes
+    pub edges: Vec<(usize, usize)>,
+    pub start: usize,
+    pub goal: usize,
+    #[serde(default)]
+    pub fog_of_war: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct LevelNode {
+    pub x: isize,
+    pub y: isize,
+    #[serde(default)]
+    pub tile: LevelTile,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LevelTile {
+    #[default]
+    Empty,
+    Treasure,
+    Enemy(EnemyKind),
+    Shop,
+}
+
+impl LevelFile {
+    pub fn load(path: &str) -> Result<LevelFile, String> {
+        let contents = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let level: LevelFile =
+            serde_json::from_str(&contents).map_err(|error| error.to_string())?;
+        level.validate()?;
+        Ok(level)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let contents = serde_json::to_string_pretty(self).map_err(|error| error.to_string())?;
+        fs::write(path, contents).map_err(|error| error.to_string())
+    }
+
+    // Checks the level can be played, the first problem found is returned
+    pub fn validate(&self) -> Result<(), String> {
+        let num_nodes = self.nodes.len();
+        let mut positions = HashSet::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let on_grid = |value: isize| (0..self.grid_size as isize).contains(&value);
+            if !on_grid(node.x) || !on_grid(node.y) {
+                return Err(format!("Node {} is off the {} grid", index, self.grid_size));
+            }
+            if !positions.insert((node.x, node.y)) {
+                return Err(format!("Node {} is on top of another node", index));
+            }
+        }
+        for (node_1, node_2) in &self.edges {
+            if *node_1 >= num_nodes || *node_2 >= num_nodes || node_1 == node_2 {
+                return Err(format!(
+                    "Edge ({}, {}) isn't between two nodes",
+                    node_1, node_2
+                ));
+            }
+        }
+        if self.start >= num_nodes || self.goal >= num_nodes {
+            return Err("The start and goal have to be nodes".to_string());
+        }
+        if self.start == self.goal {
+            return Err("The start and goal have to be different nodes".to_string());
+        }
+        for (name, index) in [("start", self.start), ("goal", self.goal)] {
+            if self.nodes[index].tile != LevelTile::Empty {
+                return Err(format!("The {} has to be empty", name));
+            }
+        }
+        let component = components(&self.graph_nodes());
+        if component[self.start] != component[self.goal] {
+            return Err("The goal can't be reached from the start".to_string());
+        }
+        Ok(())
+    }
+
+    fn graph_nodes(&self) -> Vec<Node> {
+        let mut nodes: Vec<Node> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| Node {
+                x: node.x,
+                y: node.y,
+                value: match node.tile {
+                    LevelTile::Empty => Tile::Empty,
+                    LevelTile::Treasure => Tile::Treasure,
+                    LevelTile::Enemy(kind) => Tile::Enemy(Enemy { kind }),
+                    LevelTile::Shop => Tile::Shop,
+                },
+                neighbors: Vec::new(),
+                index,
+            })
+            .collect();
+        for (node_1, node_2) in &self.edges {
+            if !nodes[*node_1].neighbors.contains(node_2) {
+                nodes[*node_1].neighbors.push(*node_2);
+                nodes[*node_2].neighbors.push(*node_1);
+            }
+        }
+        nodes
+    }
+
+    // The level is guarded by the boss of the built in level with the same background, which
+    // gives the same reward
+    pub fn level_info(&self) -> LevelInfo {
+        let built_in = Graph::levels()
+            .into_iter()
+            .find(|level| level.background == self.background)
+            .unwrap_or_else(|| Graph::levels().remove(0));
+        let color = |[r, g, b]: [u8; 3]| Color::from_rgba(r, g, b, 255);
+        LevelInfo {
+            background: self.background,
+            ground_color: color(self.ground_color),
+            sky_color: color(self.sky_color),
+            boss: built_in.boss,
+            boss_reward: built_in.boss_reward,
+            generator: Box::new(Handmade {
+                grid_size: self.grid_size,
+                positions: self.nodes.iter().map(|node| (node.x, node.y)).collect(),
+                edges: self.edges.clone(),
+            }),
+        }
+    }
+
+    pub fn layout(&self) -> MapLayout {
+        let count = |tile: fn(&LevelTile) -> bool| {
+            self.nodes.iter().filter(|node| tile(&node.tile)).count()
+        };
+        MapLayout {
+            grid_size: self.grid_size,
+            num_nodes: self.nodes.len(),
+            num_enemies: count(|tile| matches!(tile, LevelTile::Enemy(_))),
+            num_treasure: count(|tile| *tile == LevelTile::Treasure),
+            fog_of_war: self.fog_of_war,
+        }
+    }
+}
+
+impl Graph {
+    // A run of just this level, the seed is still used for everything random during it
+    pub fn from_level(seed: u64, level: LevelFile) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        Graph {
+            seed,
+            layout: level.layout(),
+            shop_stock: shop_stock(&mut rng),
+            rng,
+            nodes: level.graph_nodes(),
+            current_player_position: Some(level.start),
+            goal_position: Some(level.goal),
+            player_path: Vec::new(),
+            visited: HashSet::from([level.start]),
+            background_order: vec![level.level_info()],
+            custom_level: Some(level),
+        }
+    }
+
+    // The graph for a new run after this one, custom levels are played again
+    pub fn next_run(&self) -> Graph {
+        match &self.custom_level {
+            Some(level) => Graph::from_level(::rand::random(), level.clone()),
+            None => Graph::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broken_level_files_are_rejected() {
+        let level = LevelFile::load("levels/tutorial.json").unwrap();
+        let broken = [
+            LevelFile {
+                goal: level.start,
+                ..level.clone()
+            },
+            LevelFile {
+                edges: vec![(0, 1)],
+                ..level.clone()
+            },
+            LevelFile {
+                edges: vec![(0, 99)],
+                ..level.clone()
+            },
+            LevelFile {
+                grid_size: 3,
+                ..level.clone()
+            },
+        ];
+        for level in broken {
+            assert!(level.validate().is_err());
+        }
+    }
+}
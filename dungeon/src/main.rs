@@ -1,41 +1,98 @@
 pub mod map;
-use crate::map::{load_map_textures, mouse_events, Graph, Tile};
+use crate::map::{
+    keyboard_events, load_map_textures, mouse_events, word_travel_events, Graph, MapCursor,
+    MapLayout, Tile,
+};
 pub mod combat;
 use crate::combat::{
-    draw_combat, enemy_attack, enter_combat_animation, load_combat_textures, typing, CombatState,
-    DeletionState, Player, SENTENCE_LOWER_BOUND, SENTENCE_UPPER_BOUND,
+    animation_finished, completed_words, correct_prefix, enemy_attack, enemy_hit,
+    load_combat_textures, num_words, typing, word_damage, DeletionState, Player,
+};
+pub mod enemy;
+use crate::enemy::{load_enemy_textures, ChallengeStyle, EnemyKind, EnemyStats, Foe};
+pub mod consumables;
+use crate::consumables::{
+    command_prefix, consumable_pressed, random_consumable, skip_word, typed_command, Consumables,
 };
+pub mod editor;
+use crate::editor::{editor_events, Editor, EditorAction};
+pub mod effects;
+use crate::effects::{CardEffects, Effect};
+pub mod generators;
+pub mod level;
+use crate::level::LevelFile;
+pub mod relics;
+use crate::relics::{relic_actions, CombatEvent, Relic, RelicAction};
 pub mod sentences;
-use crate::sentences::{load_sentences, return_sentence};
-use crate::treasure::{card_select, load_treasure_images, CardType, PERM_CARDS, TEMP_CARDS};
+use crate::sentences::{corpus_select, load_sentences, return_sentence, BUILT_IN_CORPUS, CORPORA};
+use crate::treasure::{
+    card_by_title, card_select, draw_cards, load_treasure_images, offered_cards, reward_layout,
+    Card, RewardChoice, RewardPool,
+};
 pub mod end;
-use crate::end::{draw_death_screen, draw_victory_screen, load_end_images, restart};
+use crate::end::{load_end_images, restart};
+pub mod save;
+use crate::save::{autosave, delete_save, load_game, save_exists};
+pub mod start;
+use crate::start::{title_select, TitleOption};
+pub mod pause;
+use crate::pause::{pause_select, Pause, PauseOption};
+pub mod shop;
+use crate::shop::{chest_gold, sentence_gold, shop_select, ShopChoice, ShopItem, HEAL_AMOUNT};
+pub mod stats;
+use crate::stats::{CombatStats, TypingEvent, TypingTracker};
+pub mod profile;
+use crate::profile::{record_run, DamageSource, Profile};
+pub mod config;
+use crate::config::{settings_select, GameConfig, Settings};
+pub mod platform;
+use crate::platform::{Clock, Input, MacroquadInput, MacroquadRenderer, Renderer, SystemClock};
+#[cfg(test)]
+mod headless;
 use ::rand::seq::SliceRandom;
 use futures::join;
 use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 pub mod treasure;
 
-#[derive(Copy, Clone, PartialEq)]
+// The gold it costs to draw a chest or boss's cards again
+const REROLL_PRICE: usize = 10;
+// Health lost for each mistyped word when travelling by words
+const TRAVEL_MISTAKE_DAMAGE: f32 = 1.;
+// The settings, profile and save file are kept next to where the game is run
+const DATA_DIR: &str = ".";
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 enum RewardType {
     Treasure,
     EndOfLevel,
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 enum EndCondition {
     Death,
     Success,
 }
 
-#[derive(PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 enum GameState {
     LoadTextures,
+    Title,
+    Settings,
+    Practice,
+    Statistics,
+    Editor,
+    Quit,
+    SelectCorpus,
     MainMap,
     EnterCombat,
     Combat,
+    CombatSummary,
     ExitCombat,
     Rewarded(RewardType),
+    Shop,
     EndOfGame(EndCondition),
 }
 
@@ -44,197 +101,618 @@ impl GameState {
         GameState::LoadTextures
     }
 
+    fn new_run() -> GameState {
+        // Only ask which corpus to use if some packs were found
+        if CORPORA.len() > 1 {
+            GameState::SelectCorpus
+        } else {
+            GameState::MainMap
+        }
+    }
+
+    // The states escape brings up the pause menu in, the other menus use it to go back
+    fn can_pause(&self) -> bool {
+        *self == GameState::Practice || self.in_run()
+    }
+
+    fn in_run(&self) -> bool {
+        !matches!(
+            self,
+            GameState::LoadTextures
+                | GameState::Title
+                | GameState::Settings
+                | GameState::Practice
+                | GameState::Statistics
+                | GameState::Editor
+                | GameState::Quit
+        )
+    }
+}
+
+// Everything that makes up a run. The game logic only talks to the outside world through
+// the Input and Clock traits so it can be driven without a window, drawing is left to a Renderer
+struct Game {
+    state: GameState,
+    graph: Graph,
+    player: Player,
+    variables: Variables,
+    profile: Profile,
+    settings: Settings,
+    // Whether the title screen can offer to continue a run
+    save_found: bool,
+    // Shown over the current state, which is frozen until it is closed
+    pause: Option<Pause>,
+    // Kept when leaving the editor so the level isn't lost
+    editor: Editor,
+    // Where the settings, profile and save file are kept
+    data_dir: PathBuf,
+}
+
+impl Game {
+    fn new(graph: Graph) -> Game {
+        Game {
+            state: GameState::new(),
+            graph,
+            player: Player::new(),
+            variables: Variables::default(),
+            profile: Profile::default(),
+            settings: Settings::default(),
+            save_found: false,
+            pause: None,
+            editor: Editor::default(),
+            data_dir: PathBuf::from(DATA_DIR),
+        }
+    }
+
     async fn load_textures(&mut self) {
         join!(
             load_sentences(),
             load_combat_textures(),
+            load_enemy_textures(),
             load_map_textures(),
             load_treasure_images(),
             load_end_images()
         );
-        *self = Self::MainMap;
+        self.profile = Profile::load(&self.data_dir);
+        self.settings = Settings::load(&self.data_dir);
+        self.save_found = save_exists(&self.data_dir);
+        self.state = GameState::Title;
     }
 
-    fn main_map_logic(&mut self, graph: &mut Graph, variables: &mut Variables, player: &Player) {
-        get_char_pressed();
-        mouse_events(graph);
-        move_player(
-            graph,
-            &mut variables.last_move,
-            self,
-            &mut variables.entered_combat,
-            &mut variables.current_background,
-        );
-        if *self == GameState::MainMap {
-            graph.draw_graph(&player.armoured, &variables.current_background);
+    // What is being fought, an enemy's tile is only cleared once the combat is over
+    fn foe(&self) -> Foe {
+        match self.variables.boss_phase {
+            Some(phase) => Foe::Boss {
+                boss: self.graph.background_order[self.variables.current_background].boss,
+                phase,
+            },
+            None => Foe::Enemy(self.graph.current_enemy().unwrap_or(EnemyKind::Gopher)),
         }
     }
 
-    fn prepare_combat(&mut self, variables: &mut Variables) {
-        match enter_combat_animation((0., 0.), &mut variables.entered_combat) {
-            CombatState::Playing => {
-                get_char_pressed();
+    fn enemy_stats(&self) -> EnemyStats {
+        self.foe().stats().scaled(&self.variables.config)
+    }
+
+    fn update(&mut self, input: &mut impl Input, clock: &impl Clock) {
+        let now = clock.now();
+        if self.pause.is_some() {
+            self.pause_logic(input, now);
+            return;
+        }
+        if self.state.can_pause() && input.key_pressed(KeyCode::Escape) {
+            self.pause = Some(Pause::new(now));
+            return;
+        }
+        self.player.health = (self.player.health * 100.).round() / 100.;
+
+        if self.player.health <= 0.0 {
+            self.state = GameState::EndOfGame(EndCondition::Death);
+        }
+        match self.state {
+            // Loading is asynchronous so it is handled by the main loop
+            GameState::LoadTextures => (),
+            GameState::Title => self.title(input),
+            GameState::Settings => self.choose_settings(input),
+            GameState::Practice => self.practice(input, now),
+            GameState::Statistics => {
+                if input.key_pressed(KeyCode::Escape) {
+                    self.state = GameState::Title;
+                }
+            }
+            GameState::Editor => self.edit_level(input),
+            // The main loop stops once it sees this
+            GameState::Quit => (),
+            GameState::SelectCorpus => self.select_corpus(input),
+            GameState::MainMap => self.main_map_logic(input, now),
+            GameState::EnterCombat => self.prepare_combat(input, now),
+            GameState::Combat => self.combat_logic(input, now),
+            GameState::CombatSummary => self.combat_summary(input),
+            GameState::ExitCombat => self.exit_combat(now),
+            GameState::Rewarded(reward_type) => self.display_and_select_rewards(reward_type, input),
+            GameState::Shop => self.shop(input),
+            GameState::EndOfGame(_) => self.display_end(input),
+        }
+    }
+
+    fn pause_logic(&mut self, input: &mut impl Input, now: Instant) {
+        // Nothing typed while paused should reach the sentence
+        while input.char_pressed().is_some() {}
+        let Some(pause) = &mut self.pause else {
+            return;
+        };
+        if pause.settings_open {
+            if settings_select(input, &mut self.settings) || input.key_pressed(KeyCode::Escape) {
+                pause.settings_open = false;
+                self.settings.save(&self.data_dir);
+                // The new difficulty is used straight away, the map size changes on the next level
+                if self.state.in_run() {
+                    self.variables.config = self.settings.config(self.settings.difficulty);
+                }
+            }
+            return;
+        }
+        match pause_select(input, &mut pause.selected) {
+            Some(PauseOption::Resume) => {
+                let paused_for = now.saturating_duration_since(pause.paused_at);
+                self.variables.shift_timers(paused_for);
+                self.pause = None;
+            }
+            Some(PauseOption::Settings) => pause.settings_open = true,
+            Some(PauseOption::SaveAndQuit) => {
+                self.pause = None;
+                autosave(self);
+                self.state = GameState::Quit;
             }
-            CombatState::Finished => {
-                variables.sentence = None;
-                while variables.sentence == None {
-                    let sentence_length = match (SENTENCE_LOWER_BOUND..SENTENCE_UPPER_BOUND)
-                        .collect::<Vec<usize>>()
-                        .choose(&mut ::rand::thread_rng())
-                    {
-                        Some(length) => ((*length + 10 * variables.current_background) as f32
-                            * variables.perm_word_reduction
-                            - variables.temp_words_reduction)
-                            .floor() as usize,
-                        None => continue,
-                    };
-                    variables.sentence = Some(match return_sentence(sentence_length) {
-                        Some(sentence) => sentence.chars().collect(),
-                        None => continue,
-                    });
+            Some(PauseOption::Abandon) => {
+                // Leaving practice keeps the dungeon, an abandoned one is replaced
+                if self.state.in_run() {
+                    delete_save(&self.data_dir);
+                    self.graph = self.graph.next_run();
                 }
-                variables.last_attack = Instant::now();
-                *self = GameState::Combat;
+                self.player = Player::new();
+                self.variables = Variables::default();
+                self.pause = None;
+                self.save_found = save_exists(&self.data_dir);
+                self.state = GameState::Title;
             }
+            None => (),
         }
     }
 
-    fn combat_logic(&mut self, player: &mut Player, variables: &mut Variables, graph: &Graph) {
-        enemy_attack(
+    fn title(&mut self, input: &mut impl Input) {
+        input.char_pressed();
+        let selected = &mut self.variables.selected_title_option;
+        match title_select(input, selected, self.save_found) {
+            Some(TitleOption::NewRun) => self.start_run(),
+            Some(TitleOption::Continue) => self.continue_run(),
+            Some(TitleOption::Settings) => self.state = GameState::Settings,
+            Some(TitleOption::Practice) => self.state = GameState::Practice,
+            Some(TitleOption::Statistics) => self.state = GameState::Statistics,
+            Some(TitleOption::Editor) => self.state = GameState::Editor,
+            Some(TitleOption::Quit) => self.state = GameState::Quit,
+            None => (),
+        }
+    }
+
+    fn start_run(&mut self) {
+        delete_save(&self.data_dir);
+        let config = self.settings.config(self.settings.difficulty);
+        // The map is built at the chosen size, the seed stays the same
+        self.graph = match self.graph.custom_level.take() {
+            Some(level) => Graph::from_level(self.graph.seed, level),
+            None => Graph::from_seed(self.graph.seed, MapLayout::new(&config)),
+        };
+        self.player = Player::new();
+        self.variables = Variables {
+            config,
+            ..Variables::default()
+        };
+        self.state = GameState::new_run();
+    }
+
+    fn continue_run(&mut self) {
+        match load_game(&self.data_dir) {
+            Some(game) => {
+                let profile = std::mem::take(&mut self.profile);
+                let settings = std::mem::take(&mut self.settings);
+                let data_dir = std::mem::take(&mut self.data_dir);
+                *self = Game {
+                    profile,
+                    settings,
+                    data_dir,
+                    ..game
+                };
+            }
+            None => self.save_found = false,
+        }
+    }
+
+    fn choose_settings(&mut self, input: &mut impl Input) {
+        if settings_select(input, &mut self.settings) || input.key_pressed(KeyCode::Escape) {
+            self.settings.save(&self.data_dir);
+            self.state = GameState::Title;
+        }
+    }
+
+    fn edit_level(&mut self, input: &mut impl Input) {
+        match editor_events(&mut self.editor, input) {
+            Some(EditorAction::Play(level)) => {
+                self.graph = Graph::from_level(self.graph.seed, level);
+                self.start_run();
+            }
+            Some(EditorAction::Quit) => self.state = GameState::Title,
+            None => (),
+        }
+    }
+
+    fn practice(&mut self, input: &mut impl Input, now: Instant) {
+        let (player, variables) = (&mut self.player, &mut self.variables);
+        if variables.sentence.is_none() {
+            self.choose_sentence();
+            self.variables.typing_tracker = Some(TypingTracker::new(now));
+            return;
+        }
+        let events = typing(
+            input,
+            &mut player.sentence,
+            &mut variables.deletion_state,
+            &mut variables.time_since_last_delete,
+            now,
+        );
+        let sentence = variables.sentence.as_ref().unwrap();
+        let tracker = variables
+            .typing_tracker
+            .get_or_insert(TypingTracker::new(now));
+        for event in events {
+            tracker.record(event, sentence, now);
+        }
+        if *sentence == player.sentence {
+            variables.practice_stats = Some(tracker.finish(sentence, now));
+            variables.sentence = None;
+            player.sentence = Vec::new();
+        }
+    }
+
+    fn select_corpus(&mut self, input: &mut impl Input) {
+        if let Some(corpus) = corpus_select(input, &mut self.variables.selected_corpus) {
+            self.variables.corpus = corpus;
+            self.state = GameState::MainMap;
+        }
+    }
+
+    fn main_map_logic(&mut self, input: &mut impl Input, now: Instant) {
+        let (graph, variables) = (&mut self.graph, &mut self.variables);
+        if self.settings.word_travel {
+            let mistakes =
+                word_travel_events(graph, &mut variables.map_cursor, &variables.corpus, input);
+            if mistakes > 0 {
+                self.player.health -= TRAVEL_MISTAKE_DAMAGE * mistakes as f32;
+                variables.last_hurt_by = Some(DamageSource::WordTravel);
+            }
+        } else {
+            keyboard_events(graph, &mut variables.map_cursor, input);
+        }
+        mouse_events(&mut self.graph, input.mouse_clicked(), input.screen_size());
+        move_player(
+            &mut self.graph,
+            &mut self.variables.last_move,
+            &mut self.state,
+            &mut self.variables.entered_combat,
+            &mut self.variables.boss_phase,
+            now,
+        );
+    }
+
+    fn prepare_combat(&mut self, input: &mut impl Input, now: Instant) {
+        if !animation_finished(self.variables.entered_combat, now) {
+            input.char_pressed();
+            return;
+        }
+        self.start_sentence(now);
+        self.variables.streak = 0;
+        self.state = GameState::Combat;
+    }
+
+    // Starts a new sentence against the current foe at full health
+    fn start_sentence(&mut self, now: Instant) {
+        self.choose_sentence();
+        let health = self.enemy_stats().health;
+        let variables = &mut self.variables;
+        variables.enemy_health = health;
+        variables.words_credited = 0;
+        variables.last_attack = now;
+        variables.typing_tracker = Some(TypingTracker::new(now));
+    }
+
+    fn choose_sentence(&mut self) {
+        let challenge = self.enemy_stats().challenge;
+        let variables = &mut self.variables;
+        let config = variables.config;
+        variables.sentence = None;
+        while variables.sentence.is_none() {
+            let sentence_length = match challenge
+                .sentence_lengths()
+                .collect::<Vec<usize>>()
+                .choose(&mut self.graph.rng)
+            {
+                Some(length) => variables.card_effects.current().sentence_length(
+                    (*length as f32 * config.sentence_length_scale) as usize
+                        + config.level_sentence_growth * variables.current_background,
+                ),
+                None => continue,
+            };
+            variables.sentence = Some(
+                match return_sentence(
+                    &variables.corpus,
+                    sentence_length,
+                    |sentence| challenge.accepts(sentence),
+                    &mut self.graph.rng,
+                ) {
+                    Some(sentence) => sentence.chars().collect(),
+                    None => continue,
+                },
+            );
+        }
+    }
+
+    fn combat_logic(&mut self, input: &mut impl Input, now: Instant) {
+        let foe = self.foe();
+        let enemy = self.enemy_stats();
+        let (player, variables) = (&mut self.player, &mut self.variables);
+        let mut relic_events = Vec::new();
+        if enemy_attack(
             player,
+            &enemy,
             &mut variables.last_attack,
-            &variables.temp_damage_reduction,
-            &variables.perm_damage_reduction,
-        );
-        let test = variables.sentence.clone();
-        typing(
+            &variables.card_effects.current(),
+            now,
+        ) {
+            relic_events.push(CombatEvent::HitTaken);
+        }
+        let events = typing(
+            input,
             &mut player.sentence,
             &mut variables.deletion_state,
             &mut variables.time_since_last_delete,
+            now,
         );
-        match {
-            let level_info = &graph.background_order[variables.current_background];
-            draw_combat(
-                &test.unwrap(),
-                player,
-                &level_info.sky_color,
-                &level_info.ground_color,
-            )
-        } {
-            CombatState::Playing => (),
-            CombatState::Finished => {
-                *self = GameState::ExitCombat;
-                player.sentence = Vec::new();
+        let mut consumable = consumable_pressed(input);
+        let sentence = variables.sentence.as_ref().unwrap();
+        let typed_correct = correct_prefix(sentence, &player.sentence);
+        let tracker = variables
+            .typing_tracker
+            .get_or_insert(TypingTracker::new(now));
+        // Commands for consumables are typed over the sentence and don't count as mistakes, until
+        // what was typed can't become one, then everything that was held back does
+        let events: Vec<TypingEvent> = events
+            .into_iter()
+            .flat_map(|event| match event {
+                TypingEvent::Typed(_, position) if position >= typed_correct => {
+                    let typed = &player.sentence[typed_correct..=position];
+                    if command_prefix(typed) {
+                        Vec::new()
+                    } else if command_prefix(&typed[..typed.len() - 1]) {
+                        (typed_correct..=position)
+                            .map(|i| TypingEvent::Typed(player.sentence[i], i))
+                            .collect()
+                    } else {
+                        vec![event]
+                    }
+                }
+                event => vec![event],
+            })
+            .collect();
+        for event in events {
+            tracker.record(event, sentence, now);
+            if let TypingEvent::Typed(c, position) = event {
+                match sentence.get(position) {
+                    Some(expected) if *expected == c => relic_events.push(CombatEvent::Keystroke),
+                    // A mistake breaks the streak, and mirrors punish wrong punctuation straight away
+                    expected => {
+                        variables.streak = 0;
+                        relic_events.push(CombatEvent::Mistake);
+                        if enemy.challenge == ChallengeStyle::Punctuation
+                            && expected.is_some_and(|expected| expected.is_ascii_punctuation())
+                        {
+                            enemy_hit(player, &enemy, &variables.card_effects.current());
+                            relic_events.push(CombatEvent::HitTaken);
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(command) = typed_command(&player.sentence[typed_correct..]) {
+            player.sentence.truncate(typed_correct);
+            consumable = Some(command);
+        }
+        if let Some(consumable) = consumable {
+            // Nothing happens if there are none left or it is cooling down
+            if variables.consumables.take(consumable, now) {
+                match consumable.effect() {
+                    Some(effect) => variables.card_effects.apply(&effect, player),
+                    None => {
+                        player.sentence = sentence[..skip_word(sentence, typed_correct)].to_vec()
+                    }
+                }
+            }
+        }
+
+        // Each word is only counted the first time it is completed
+        let correct = correct_prefix(sentence, &player.sentence);
+        let base_damage = enemy.health / num_words(sentence) as f32;
+        while variables.words_credited < completed_words(sentence, &player.sentence) {
+            variables.words_credited += 1;
+            variables.enemy_health -= word_damage(
+                base_damage,
+                tracker.current_wpm(correct, now),
+                variables.streak,
+            );
+            variables.streak += 1;
+            relic_events.push(CombatEvent::WordComplete);
+        }
+        let finished = *sentence == player.sentence;
+        if relic_events.contains(&CombatEvent::HitTaken) {
+            variables.last_hurt_by = Some(DamageSource::Foe);
+        }
+        variables.apply_relics(&relic_events, player, &enemy);
+
+        if variables.enemy_health <= 0.001 || finished {
+            variables.enemy_health = 0.;
+            let sentence = variables.sentence.as_ref().unwrap();
+            let tracker = variables.typing_tracker.as_mut().unwrap();
+            let stats = tracker.finish(&sentence[..correct], now);
+            variables.gold += sentence_gold(&stats, variables.boss_phase.is_some());
+            variables.combat_stats.push(stats);
+            variables.typing_tracker = None;
+            player.sentence = Vec::new();
+            match foe {
+                // Bosses go straight into their next phase with a new sentence
+                Foe::Boss { boss, phase } if phase + 1 < boss.phases => {
+                    variables.boss_phase = Some(phase + 1);
+                    self.start_sentence(now);
+                }
+                _ => {
+                    variables.apply_relics(&[CombatEvent::CombatEnd], player, &enemy);
+                    self.state = GameState::CombatSummary;
+                }
             }
         }
     }
 
-    fn exit_combat(&mut self, variables: &mut Variables, graph: &mut Graph, player: &mut Player) {
-        variables.temp_damage_reduction = 0.0;
-        player.armoured = false;
+    fn combat_summary(&mut self, input: &mut impl Input) {
+        input.char_pressed();
+        if input.key_pressed(KeyCode::Enter) || input.mouse_clicked().is_some() {
+            self.state = GameState::ExitCombat;
+        }
+    }
+
+    fn exit_combat(&mut self, now: Instant) {
+        let (graph, player, variables) = (&mut self.graph, &mut self.player, &mut self.variables);
+        variables.card_effects.end_brawl(player);
+        variables.consumables.end_brawl();
         variables.num_enemies_defeated += 1;
-        match enter_combat_animation((0., 0.), &mut variables.entered_combat) {
-            CombatState::Playing => (),
-            CombatState::Finished => {
-                graph.nodes[graph.current_player_position.unwrap()].value = Tile::Empty;
-                *self = GameState::MainMap;
-                variables.last_move = Instant::now();
+        if animation_finished(variables.entered_combat, now) {
+            graph.nodes[graph.current_player_position.unwrap()].value = Tile::Empty;
+            self.state = GameState::MainMap;
+            variables.last_move = now;
+            // Beating the boss ends the level (or the game)
+            if variables.boss_phase.take().is_some() {
+                variables.explored.push(graph.explored());
+                variables.current_background += 1;
+                if variables.current_background == graph.background_order.len() {
+                    self.state = GameState::EndOfGame(EndCondition::Success);
+                } else {
+                    graph.reload(
+                        variables.current_background,
+                        MapLayout::new(&variables.config),
+                    );
+                    variables.map_cursor = MapCursor::default();
+                    self.state = GameState::Rewarded(RewardType::EndOfLevel);
+                }
             }
         }
     }
 
-    fn display_and_select_rewards(
-        &mut self,
-        reward_type: RewardType,
-        variables: &mut Variables,
-        graph: &mut Graph,
-        player: &mut Player,
-    ) {
-        let cards = match reward_type {
-            RewardType::Treasure => TEMP_CARDS.clone(),
-            RewardType::EndOfLevel => PERM_CARDS.clone(),
-        };
-        let cards_and_coords = vec![
-            (
-                cards[0].clone(),
-                (
-                    screen_width() / 2. - cards[0].card_width * 1.2 - cards[0].card_width / 2.,
-                    screen_height() / 2. - cards[0].card_height / 2.,
-                ),
-            ),
-            (
-                cards[1].clone(),
-                (
-                    screen_width() / 2. - cards[0].card_width / 2.,
-                    screen_height() / 2. - cards[0].card_height / 2.,
-                ),
-            ),
-            (
-                cards[2].clone(),
-                (
-                    screen_width() / 2. + cards[0].card_width * 1.2 - cards[0].card_width / 2.,
-                    screen_height() / 2. - cards[0].card_height / 2.,
-                ),
-            ),
-        ];
-        graph.draw_graph(&player.armoured, &variables.current_background);
-        for (card, (x, y)) in &cards_and_coords {
-            card.draw_card(*x, *y);
-        }
-
-        if let Some(card) = card_select(&cards_and_coords) {
-            match card.card_type {
-                CardType::TempHeal => {
-                    player.health += 40.;
-                    if player.health > player.max_health {
-                        player.health = player.max_health;
+    // What the chest or the boss of the level that was just left offers
+    fn reward_pool(&self, reward_type: RewardType) -> RewardPool {
+        match reward_type {
+            RewardType::Treasure => RewardPool::CHEST,
+            RewardType::EndOfLevel => {
+                let level = self.variables.current_background.saturating_sub(1);
+                self.graph.background_order[level].boss_reward
+            }
+        }
+    }
+
+    fn display_and_select_rewards(&mut self, reward_type: RewardType, input: &mut impl Input) {
+        let reward_pool = self.reward_pool(reward_type);
+        let (graph, player, variables) = (&mut self.graph, &mut self.player, &mut self.variables);
+        if variables.reward_offer.is_empty() {
+            variables.reward_offer = draw_cards(&reward_pool, &mut graph.rng);
+        }
+        let cards_and_coords =
+            reward_layout(&offered_cards(&variables.reward_offer), input.screen_size());
+
+        let choice = card_select(&cards_and_coords, input);
+        match choice {
+            Some(RewardChoice::Card(index)) => {
+                apply_card(&cards_and_coords[index].0, player, variables)
+            }
+            // Nothing happens if the player can't afford it
+            Some(RewardChoice::Reroll) if variables.gold >= REROLL_PRICE => {
+                variables.gold -= REROLL_PRICE;
+                variables.reward_offer = draw_cards(&reward_pool, &mut graph.rng);
+                return;
+            }
+            Some(RewardChoice::Skip) => (),
+            _ => return,
+        }
+        // Skipping still empties the chest
+        variables.reward_offer.clear();
+        if graph.nodes[graph.current_player_position.unwrap()].value == Tile::Treasure {
+            graph.nodes[graph.current_player_position.unwrap()].value = Tile::Empty;
+            variables.gold += chest_gold(&variables.combat_stats);
+            // Lost if the player already carries as many of it as they can
+            variables.consumables.add(random_consumable(&mut graph.rng));
+        }
+        self.state = GameState::MainMap;
+    }
+
+    fn shop(&mut self, input: &mut impl Input) {
+        input.char_pressed();
+        let (graph, player, variables) = (&mut self.graph, &mut self.player, &mut self.variables);
+        match shop_select(&graph.shop_stock, input) {
+            // Nothing happens if the player can't buy it
+            Some(ShopChoice::Buy(index))
+                if graph.shop_stock[index].can_buy(variables.gold, &variables.relics) =>
+            {
+                variables.gold -= graph.shop_stock[index].price();
+                match graph.shop_stock.remove(index) {
+                    ShopItem::Heal => variables.card_effects.apply(
+                        &Effect::Heal {
+                            amount: HEAL_AMOUNT,
+                        },
+                        player,
+                    ),
+                    ShopItem::Card(title) => {
+                        if let Some(card) = card_by_title(&title) {
+                            apply_card(card, player, variables)
+                        }
                     }
+                    ShopItem::Relic(relic) => variables.relics.push(relic),
                 }
-                CardType::TempDamageReduction => {
-                    player.armoured = true;
-                    variables.temp_damage_reduction += 1.;
-                }
-                CardType::TempWordsReduce => variables.temp_words_reduction *= 0.90,
-                CardType::PermHeal => player.max_health *= 1.10,
-                CardType::PermDamageReduction => variables.perm_damage_reduction *= 0.95,
-                CardType::PermWordsReduce => variables.perm_word_reduction *= 0.90,
-            };
-            if graph.nodes[graph.current_player_position.unwrap()].value == Tile::Treasure {
-                graph.nodes[graph.current_player_position.unwrap()].value = Tile::Empty;
             }
-            *self = GameState::MainMap;
+            Some(ShopChoice::Leave) => self.state = GameState::MainMap,
+            _ => (),
         }
     }
 
-    fn display_end(
-        &mut self,
-        variables: &mut Variables,
-        graph: &mut Graph,
-        end_type: EndCondition,
-        player: &mut Player,
-    ) {
-        match end_type {
-            EndCondition::Death => draw_death_screen(
-                &(variables.current_background + 1),
-                &variables.num_enemies_defeated,
-            ),
-            EndCondition::Success => draw_victory_screen(&variables.num_enemies_defeated),
-        }
-        if restart() {
-            *player = Player::default();
-            *self = GameState::new();
-            *graph = Graph::default();
-            *variables = Variables::default();
+    fn display_end(&mut self, input: &mut impl Input) {
+        if restart(input) {
+            let data_dir = std::mem::take(&mut self.data_dir);
+            *self = Game {
+                data_dir,
+                ..Game::new(self.graph.next_run())
+            };
         }
     }
 }
 
+fn apply_card(card: &Card, player: &mut Player, variables: &mut Variables) {
+    for effect in &card.effects {
+        variables.card_effects.apply(effect, player);
+    }
+}
+
 fn move_player(
     graph: &mut Graph,
     last_move: &mut Instant,
     game_state: &mut GameState,
     entered_combat: &mut Option<Instant>,
-    current_background: &mut usize,
+    boss_phase: &mut Option<usize>,
+    now: Instant,
 ) {
     let movement_speed = 0.01;
     if !graph.player_path.is_empty() {
@@ -243,28 +721,32 @@ fn move_player(
             *graph.player_path.last().unwrap(),
         );
         let travel_time = Duration::from_millis((distance / movement_speed).round() as u64);
-        if last_move.elapsed() >= travel_time {
+        if now.saturating_duration_since(*last_move) >= travel_time {
             let next_pos = graph.player_path.pop().unwrap();
+            // Paths start on the node the player is already on, which shouldn't be entered again
+            if Some(next_pos) == graph.current_player_position {
+                return;
+            }
+            graph.move_player(next_pos);
+            *last_move = now;
 
-            // End the level (or the game) and load the next game state
+            // The level's boss has to be beaten before the level can be left
             if next_pos == graph.goal_position.unwrap() {
-                *current_background += 1;
-                if *current_background == graph.background_order.len() {
-                    *game_state = GameState::EndOfGame(EndCondition::Success);
-                    return;
-                }
-                *game_state = GameState::Rewarded(RewardType::EndOfLevel);
+                graph.player_path.clear();
+                *boss_phase = Some(0);
+                *game_state = GameState::EnterCombat;
+                entered_combat.replace(now);
+                return;
             }
-            graph.move_player(next_pos);
-            *last_move = Instant::now();
 
             match graph.nodes[graph.current_player_position.unwrap()].value {
                 Tile::Empty => (),
                 Tile::Enemy(_) => {
                     *game_state = GameState::EnterCombat;
-                    entered_combat.replace(Instant::now());
+                    entered_combat.replace(now);
                 }
                 Tile::Treasure => *game_state = GameState::Rewarded(RewardType::Treasure),
+                Tile::Shop => *game_state = GameState::Shop,
             }
         }
     }
@@ -273,23 +755,81 @@ fn move_player(
 fn window_conf() -> Conf {
     Conf {
         window_title: "Dungeon Explorer".to_owned(),
-        fullscreen: true,
+        // Everything is drawn at a virtual resolution so any window size works
+        fullscreen: Settings::load(Path::new(DATA_DIR)).fullscreen,
+        window_width: 1280,
+        window_height: 720,
+        window_resizable: true,
+        high_dpi: true,
         ..Default::default()
     }
 }
+#[derive(Serialize, Deserialize)]
 struct Variables {
+    #[serde(skip, default = "Instant::now")]
     last_move: Instant,
+    #[serde(skip)]
     entered_combat: Option<Instant>,
+    #[serde(skip)]
     sentence: Option<Vec<char>>,
+    #[serde(skip, default = "Instant::now")]
     time_since_last_delete: Instant,
+    #[serde(skip)]
     deletion_state: DeletionState,
+    #[serde(skip, default = "Instant::now")]
     last_attack: Instant,
-    temp_damage_reduction: f32,
+    #[serde(skip)]
+    typing_tracker: Option<TypingTracker>,
+    // Combat restarts from the beginning when a save is loaded so none of this is saved
+    #[serde(skip)]
+    enemy_health: f32,
+    #[serde(skip)]
+    words_credited: usize,
+    // Words typed correctly in a row
+    #[serde(skip)]
+    streak: usize,
+    // The typing stats of every combat won this run
+    #[serde(default)]
+    combat_stats: Vec<CombatStats>,
     current_background: usize,
-    temp_words_reduction: f32,
-    perm_word_reduction: f32,
-    perm_damage_reduction: f32,
+    #[serde(default)]
+    card_effects: CardEffects,
     num_enemies_defeated: usize,
+    #[serde(default = "Variables::built_in_corpus")]
+    corpus: String,
+    // The phase of the boss being fought, if it is being fought
+    #[serde(default)]
+    boss_phase: Option<usize>,
+    // The difficulty the run was started with
+    #[serde(default)]
+    config: GameConfig,
+    // How much of each finished level was explored
+    #[serde(default)]
+    explored: Vec<f32>,
+    // Earned in combat and from chests, spent in shops
+    #[serde(default)]
+    gold: usize,
+    // Found this run, in the order they were found
+    #[serde(default)]
+    relics: Vec<Relic>,
+    // Found in chests and used in combat
+    #[serde(default)]
+    consumables: Consumables,
+    // The titles of the cards on offer from the current chest or boss
+    #[serde(default)]
+    reward_offer: Vec<String>,
+    #[serde(skip)]
+    map_cursor: MapCursor,
+    #[serde(skip)]
+    selected_corpus: usize,
+    #[serde(skip)]
+    selected_title_option: usize,
+    // The stats of the last sentence typed in practice mode
+    #[serde(skip)]
+    practice_stats: Option<CombatStats>,
+    // What last took some of the player's health, to record what killed them
+    #[serde(skip)]
+    last_hurt_by: Option<DamageSource>,
 }
 impl Default for Variables {
     fn default() -> Self {
@@ -300,50 +840,101 @@ impl Default for Variables {
             time_since_last_delete: Instant::now(),
             deletion_state: DeletionState::FirstCharacter,
             last_attack: Instant::now(),
-            temp_damage_reduction: 0.0,
+            typing_tracker: None,
+            enemy_health: 0.,
+            words_credited: 0,
+            streak: 0,
+            combat_stats: Vec::new(),
+            gold: 0,
+            relics: Vec::new(),
+            consumables: Consumables::default(),
+            reward_offer: Vec::new(),
             current_background: 0,
-            temp_words_reduction: 1.,
-            perm_word_reduction: 1.,
-            perm_damage_reduction: 1.,
+            card_effects: CardEffects::default(),
             num_enemies_defeated: 0,
+            corpus: Self::built_in_corpus(),
+            boss_phase: None,
+            config: GameConfig::default(),
+            explored: Vec::new(),
+            map_cursor: MapCursor::default(),
+            selected_corpus: 0,
+            selected_title_option: 0,
+            practice_stats: None,
+            last_hurt_by: None,
         }
     }
 }
 
+impl Variables {
+    fn apply_relics(&mut self, events: &[CombatEvent], player: &mut Player, enemy: &EnemyStats) {
+        for action in relic_actions(&self.relics, events) {
+            match action {
+                RelicAction::Heal(amount) => {
+                    self.card_effects.apply(&Effect::Heal { amount }, player)
+                }
+                RelicAction::Gold(gold) => self.gold += gold,
+                RelicAction::DamageEnemy(fraction) => self.enemy_health -= enemy.health * fraction,
+                RelicAction::DelayAttack(delay) => self.last_attack += delay,
+            }
+        }
+    }
+
+    fn built_in_corpus() -> String {
+        BUILT_IN_CORPUS.to_string()
+    }
+
+    // Pushes every timer forward so nothing happens because of the time spent paused
+    fn shift_timers(&mut self, paused_for: Duration) {
+        self.last_move += paused_for;
+        self.last_attack += paused_for;
+        self.time_since_last_delete += paused_for;
+        if let Some(entered_combat) = &mut self.entered_combat {
+            *entered_combat += paused_for;
+        }
+        if let Some(tracker) = &mut self.typing_tracker {
+            tracker.shift(paused_for);
+        }
+        self.consumables.shift(paused_for);
+    }
+}
+
 #[macroquad::main(window_conf)]
 async fn main() {
-    let mut player = Player::new();
-    let mut game_state = GameState::new();
-    let mut graph = Graph::new();
-    let mut variables = Variables::default();
+    // A seed can be passed as the first argument to replay a specific dungeon, or a level
+    // file to play that level
+    let graph = match std::env::args().nth(1) {
+        Some(arg) => match arg.parse() {
+            Ok(seed) => Graph::from_seed(seed, MapLayout::default()),
+            Err(_) => match LevelFile::load(&arg) {
+                Ok(level) => Graph::from_level(::rand::random(), level),
+                Err(error) => {
+                    println!("Failed to load the level {}: {}", arg, error);
+                    Graph::default()
+                }
+            },
+        },
+        None => Graph::default(),
+    };
+    let mut game = Game::new(graph);
+    let (mut input, clock, mut renderer) = (MacroquadInput, SystemClock, MacroquadRenderer);
 
     loop {
-        player.health = (player.health * 100.).round() / 100.;
-
-        if player.health <= 0.0 {
-            game_state = GameState::EndOfGame(EndCondition::Death);
-        }
-        clear_background(WHITE);
-        match game_state {
-            GameState::LoadTextures => game_state.load_textures().await,
-            GameState::MainMap => game_state.main_map_logic(&mut graph, &mut variables, &player),
-            GameState::EnterCombat => game_state.prepare_combat(&mut variables),
-            GameState::Combat => game_state.combat_logic(&mut player, &mut variables, &graph),
-            GameState::ExitCombat => {
-                game_state.exit_combat(&mut variables, &mut graph, &mut player);
-            }
-            GameState::Rewarded(reward_type) => {
-                game_state.display_and_select_rewards(
-                    reward_type,
-                    &mut variables,
-                    &mut graph,
-                    &mut player,
-                );
-            }
-            GameState::EndOfGame(end_type) => {
-                game_state.display_end(&mut variables, &mut graph, end_type, &mut player);
+        let previous_state = game.state;
+        if game.state == GameState::LoadTextures {
+            game.load_textures().await;
+        } else {
+            game.update(&mut input, &clock);
+        }
+        renderer.draw(&game, clock.now());
+        if game.state != previous_state {
+            autosave(&game);
+            if let GameState::EndOfGame(end_type) = game.state {
+                record_run(&mut game, end_type);
             }
         }
+        if game.state == GameState::Quit {
+            break;
+        }
         next_frame().await;
     }
 }
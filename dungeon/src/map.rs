@@ -1,24 +1,35 @@
-use ::rand::{seq::SliceRandom, Rng};
+use crate::config::GameConfig;
+use crate::enemy::{draw_enemy, enemy_size, Boss, EnemyKind, Foe};
+use crate::generators::{
+    connect_components, Corridors, Gabriel, Handmade, LoopedTree, MapGenerator,
+};
+use crate::level::LevelFile;
+use crate::platform::{virtual_height, virtual_width, Input};
+use crate::sentences::return_words;
+use crate::shop::{shop_stock, ShopItem};
+use crate::treasure::RewardPool;
+use ::rand::{seq::SliceRandom, Rng, SeedableRng};
 use lazy_static::{initialize, lazy_static};
 use macroquad::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::max,
     collections::{HashSet, VecDeque},
+    time::Duration,
 };
 
-pub const GRID_SIZE: usize = 6;
-const NODE_SIZE: f32 = 100.;
+pub const NODE_SIZE: f32 = 100.;
 const EDGE_SIZE: f32 = 10.;
 const PLAYER_SIZE: f32 = NODE_SIZE / 1.5;
 const ENEMY_SIZE: f32 = NODE_SIZE / 1.5;
 const GOAL_SIZE: f32 = NODE_SIZE / 1.5;
 const TREASURE_SIZE: f32 = NODE_SIZE / 1.5;
+const LABEL_FONT_SIZE: u16 = 36;
+// Labels are made from these, the home row comes first so the most common labels are easy to reach
+const LABEL_LETTERS: &str = "asdfghjklqwertyuiopzxcvbnm";
 
 lazy_static! {
-    pub static ref NUM_NODES: usize = (GRID_SIZE as f32).powf(1.5).round() as usize;
-    pub static ref NUM_TREASURE: usize = max(1, (*NUM_NODES as f32 / 10.).round() as usize);
-    pub static ref NUM_ENEMIES: usize = *NUM_NODES / 5;
-    // pub static ref NUM_ENEMIES: usize = 0;
     pub static ref NODE_TEXTURE: Texture2D = Texture2D::from_file_with_format(
         include_bytes!("../assets/node.png"),
         Some(ImageFormat::Png),
@@ -27,10 +38,6 @@ lazy_static! {
         include_bytes!("../assets/ferris-front.png"),
         Some(ImageFormat::Png),
     );
-    pub static ref ENEMY_TEXTURE: Texture2D = Texture2D::from_file_with_format(
-        include_bytes!("../assets/go-gopher.png"),
-        Some(ImageFormat::Png)
-    );
     pub static ref GOAL_TEXTURE: Texture2D = Texture2D::from_file_with_format(
         include_bytes!("../assets/logo.png"),
         Some(ImageFormat::Png),
@@ -68,8 +75,6 @@ pub async fn load_map_textures() {
     println!("Map player texture loaded");
     initialize(&PLAYER_ARMOURED_TEXTURE);
     println!("Map armoured player texture loaded");
-    initialize(&ENEMY_TEXTURE);
-    println!("Map enemy texture loaded");
     initialize(&NODE_TEXTURE);
     println!("Map node texture loaded");
     initialize(&GOAL_TEXTURE);
@@ -90,23 +95,29 @@ enum Sprite {
     Node,
     Player,
     PlayerArmoured,
-    Enemy,
+    Enemy(EnemyKind),
+    Boss(Boss),
     Goal,
     Treasure,
+    Shop,
 }
 
-#[derive(Eq, PartialEq, Hash, Clone, Debug, Default)]
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Default, Serialize, Deserialize)]
 pub enum Tile {
     #[default]
     Empty,
     Enemy(Enemy),
     Treasure,
+    // Stays on the map after it is visited so it can be used again
+    Shop,
 }
 
-#[derive(Eq, PartialEq, Hash, Clone, Debug)]
-pub struct Enemy {}
+#[derive(Eq, PartialEq, Hash, Clone, Debug, Serialize, Deserialize)]
+pub struct Enemy {
+    pub kind: EnemyKind,
+}
 
-#[derive(Eq, Hash, PartialEq, Clone, Debug, Default)]
+#[derive(Eq, Hash, PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Node {
     pub x: isize,
     pub y: isize,
@@ -115,70 +126,305 @@ pub struct Node {
     pub index: usize,
 }
 
+// How big the map is and how much is on it, worked out from the GameConfig of the run
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct MapLayout {
+    pub grid_size: usize,
+    pub num_nodes: usize,
+    pub num_enemies: usize,
+    pub num_treasure: usize,
+    #[serde(default)]
+    pub fog_of_war: bool,
+}
+
+impl Default for MapLayout {
+    fn default() -> Self {
+        Self::new(&GameConfig::default())
+    }
+}
+
+impl MapLayout {
+    pub fn new(config: &GameConfig) -> Self {
+        let grid_size = config.grid_size.max(2);
+        let num_nodes = (grid_size as f32).powf(1.5).round() as usize;
+        let num_treasure = max(
+            1,
+            (num_nodes as f32 * config.treasure_ratio).round() as usize,
+        );
+        // Leave room for the player and the goal
+        let num_treasure = num_treasure.min(num_nodes - 2);
+        let num_enemies =
+            ((num_nodes as f32 * config.enemy_ratio) as usize).min(num_nodes - 2 - num_treasure);
+        MapLayout {
+            grid_size,
+            num_nodes,
+            num_enemies,
+            num_treasure,
+            fog_of_war: config.fog_of_war,
+        }
+    }
+}
+
+// Where the player is planning to go when moving with the keyboard
+#[derive(Default)]
+pub struct MapCursor {
+    pub node: Option<usize>,
+    // The start of a node's label typed so far
+    pub typed: String,
+    // When travelling by words, the word to type for each neighbouring node
+    pub words: Vec<(usize, String)>,
+    // Where the player was when the words were chosen
+    pub words_at: Option<usize>,
+    // Whether the word being typed has already gone wrong
+    pub mistyped: bool,
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Graph {
+    pub seed: u64,
+    #[serde(default)]
+    pub layout: MapLayout,
+    pub rng: ChaCha8Rng,
     pub nodes: Vec<Node>,
     pub current_player_position: Option<usize>,
     pub goal_position: Option<usize>,
     pub player_path: Vec<usize>,
+    // Every node the player has stood on this level
+    #[serde(default)]
+    pub visited: HashSet<usize>,
+    // Textures can't be saved, the levels are always the same so rebuild them on load
+    #[serde(skip, default = "Graph::levels")]
     pub background_order: Vec<LevelInfo>,
+    // What the level's shop has left to sell
+    #[serde(default)]
+    pub shop_stock: Vec<ShopItem>,
+    // The level file being played instead of the generated levels
+    #[serde(default)]
+    pub custom_level: Option<LevelFile>,
+}
+
+// Backgrounds are looked up when drawn so a graph can be built without a window
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Background {
+    Forest,
+    Desert,
+    Cavern,
+    Dungeon,
+}
+
+impl Background {
+    pub const ALL: [Background; 4] = [
+        Background::Forest,
+        Background::Desert,
+        Background::Cavern,
+        Background::Dungeon,
+    ];
+
+    pub fn texture(&self) -> Texture2D {
+        match self {
+            Background::Forest => *FOREST_BACKGROUND_TEXTURE,
+            Background::Desert => *DESERT_BACKGROUND_TEXTURE,
+            Background::Cavern => *CAVERN_BACKGROUND_TEXTURE,
+            Background::Dungeon => *DUNGEON_BACKGROUND_TEXTURE,
+        }
+    }
 }
 
 pub struct LevelInfo {
-    pub map_texture: Texture2D,
+    pub background: Background,
     pub ground_color: Color,
     pub sky_color: Color,
+    // Fought on the goal node before the level can be left
+    pub boss: Boss,
+    // The cards offered for beating the boss
+    pub boss_reward: RewardPool,
+    // How the level's nodes are laid out and connected
+    pub generator: Box<dyn MapGenerator>,
+}
+
+// The dungeon is built by hand, rooms around a central hall with dead ends off the sides
+fn dungeon_layout() -> Handmade {
+    Handmade {
+        grid_size: 8,
+        positions: vec![
+            (0, 3),
+            (1, 3),
+            (2, 3),
+            (2, 1),
+            (2, 5),
+            (3, 1),
+            (3, 5),
+            (4, 1),
+            (4, 3),
+            (4, 5),
+            (5, 1),
+            (5, 5),
+            (6, 3),
+            (7, 3),
+            (1, 0),
+            (1, 6),
+            (6, 0),
+            (6, 7),
+            (0, 5),
+        ],
+        edges: vec![
+            (0, 1),
+            (1, 2),
+            (2, 3),
+            (2, 4),
+            (2, 8),
+            (3, 5),
+            (4, 6),
+            (5, 7),
+            (6, 9),
+            (7, 8),
+            (8, 9),
+            (8, 12),
+            (7, 10),
+            (9, 11),
+            (10, 12),
+            (11, 12),
+            (12, 13),
+            (3, 14),
+            (4, 15),
+            (10, 16),
+            (11, 17),
+            (4, 18),
+        ],
+    }
 }
 
 impl Default for Graph {
     fn default() -> Self {
-        Self::new()
+        Self::new(MapLayout::default())
     }
 }
 
 impl Graph {
-    pub fn new() -> Self {
-        // Create a default graph then add the nodes, connect them and specialize them
+    pub fn new(layout: MapLayout) -> Self {
+        Self::from_seed(::rand::thread_rng().gen(), layout)
+    }
+
+    pub fn from_seed(seed: u64, layout: MapLayout) -> Self {
+        // Every random decision made during the run is drawn from this rng, so
+        // the same seed (and the same moves) always gives the same run
         let mut graph = Graph {
+            seed,
+            layout,
+            rng: ChaCha8Rng::seed_from_u64(seed),
             nodes: Vec::new(),
             current_player_position: None,
             goal_position: None,
             player_path: Vec::new(),
-            background_order: vec![
-                LevelInfo {
-                    map_texture: *FOREST_BACKGROUND_TEXTURE,
-                    ground_color: GREEN,
-                    sky_color: BLUE,
-                },
-                LevelInfo {
-                    map_texture: *DESERT_BACKGROUND_TEXTURE,
-                    ground_color: BEIGE,
-                    sky_color: DARKBLUE,
-                },
-                LevelInfo {
-                    map_texture: *CAVERN_BACKGROUND_TEXTURE,
-                    ground_color: GRAY,
-                    sky_color: BLACK,
-                },
-                LevelInfo {
-                    map_texture: *DUNGEON_BACKGROUND_TEXTURE,
-                    ground_color: Color::from_rgba(149, 123, 111, 255),
-                    sky_color: BLACK,
-                },
-            ],
+            visited: HashSet::new(),
+            background_order: Self::levels(),
+            shop_stock: Vec::new(),
+            custom_level: None,
         };
-        graph.create_nodes();
-        graph.connect_nodes();
-        graph.populate_board();
+        graph.generate(0);
         graph
     }
 
-    pub fn add_node(&mut self, node: Node) {
-        self.nodes.push(node);
+    pub fn levels() -> Vec<LevelInfo> {
+        vec![
+            LevelInfo {
+                background: Background::Forest,
+                ground_color: GREEN,
+                sky_color: BLUE,
+                boss: Boss {
+                    name: "Dire Wolf",
+                    sprite: Rect::new(80., 32., 16., 16.),
+                    phases: 2,
+                    attack_interval: Duration::from_millis(2500),
+                    damage: 4.,
+                    health: 60.,
+                    speed_up: 0.8,
+                },
+                boss_reward: RewardPool {
+                    cards: 3,
+                    weights: [50, 40, 10],
+                    permanent: true,
+                },
+                generator: Box::new(LoopedTree { extra_edges: 0.15 }),
+            },
+            LevelInfo {
+                background: Background::Desert,
+                ground_color: BEIGE,
+                sky_color: DARKBLUE,
+                boss: Boss {
+                    name: "Giant Scorpion",
+                    sprite: Rect::new(48., 64., 16., 16.),
+                    phases: 3,
+                    attack_interval: Duration::from_millis(2500),
+                    damage: 4.,
+                    health: 90.,
+                    speed_up: 0.8,
+                },
+                boss_reward: RewardPool {
+                    cards: 3,
+                    weights: [40, 45, 15],
+                    permanent: true,
+                },
+                generator: Box::new(Gabriel { keep: 0.4 }),
+            },
+            LevelInfo {
+                background: Background::Cavern,
+                ground_color: GRAY,
+                sky_color: BLACK,
+                boss: Boss {
+                    name: "Flame Knight",
+                    sprite: Rect::new(48., 80., 16., 16.),
+                    phases: 3,
+                    attack_interval: Duration::from_millis(2200),
+                    damage: 5.,
+                    health: 90.,
+                    speed_up: 0.75,
+                },
+                boss_reward: RewardPool {
+                    cards: 4,
+                    weights: [35, 45, 20],
+                    permanent: true,
+                },
+                generator: Box::new(Corridors {
+                    max_branch_length: 3,
+                }),
+            },
+            LevelInfo {
+                background: Background::Dungeon,
+                ground_color: Color::from_rgba(149, 123, 111, 255),
+                sky_color: BLACK,
+                boss: Boss {
+                    name: "Bone King",
+                    sprite: Rect::new(48., 96., 16., 16.),
+                    phases: 4,
+                    attack_interval: Duration::from_millis(2200),
+                    damage: 6.,
+                    health: 120.,
+                    speed_up: 0.75,
+                },
+                boss_reward: RewardPool {
+                    cards: 4,
+                    weights: [30, 45, 25],
+                    permanent: true,
+                },
+                generator: Box::new(dungeon_layout()),
+            },
+        ]
     }
 
-    pub fn add_edge(&mut self, node_1: usize, node_2: usize) {
-        self.nodes[node_1].neighbors.push(node_2);
-        self.nodes[node_2].neighbors.push(node_1);
+    fn generate(&mut self, level: usize) {
+        // Lay out and connect the nodes the way the level asks, then specialize them
+        let generator = &self.background_order[level].generator;
+        self.nodes = generator.generate(&mut self.layout, &mut self.rng);
+        connect_components(&mut self.nodes);
+        // Hand made levels might have fewer nodes than the layout wanted things on them
+        let num_free_nodes = self.nodes.len().saturating_sub(2);
+        self.layout.num_treasure = self.layout.num_treasure.min(num_free_nodes);
+        self.layout.num_enemies = self
+            .layout
+            .num_enemies
+            .min(num_free_nodes - self.layout.num_treasure);
+        self.populate_board();
     }
 
     pub fn get_node(&mut self, x: isize, y: isize) -> Option<usize> {
@@ -191,101 +437,6 @@ impl Graph {
         None
     }
 
-    fn distance_between_nodes(node_1: &Node, node_2: &Node) -> f32 {
-        (((node_1.x - node_2.x) as f32).powf(2.) + ((node_1.y - node_2.y) as f32).powf(2.))
-            .abs()
-            .sqrt()
-    }
-
-    pub fn closest_node(
-        &self,
-        node_indices: &[usize],
-        current_node_index: &usize,
-    ) -> Option<usize> {
-        // Converts the index into a reference to the current node
-        let current_node = &self.nodes[*current_node_index];
-
-        // Loop through the nodes and return the closest_node
-        let mut closest_node: Option<usize> = None;
-        let mut closest_distance = f32::INFINITY;
-
-        for index in node_indices.iter() {
-            let node = &self.nodes[*index];
-
-            let distance = Self::distance_between_nodes(current_node, node);
-
-            // Update the closest_node and distance if the
-            // current node is closer to the node of interest
-            if distance < closest_distance {
-                closest_distance = distance;
-                closest_node = Some(*index);
-            }
-        }
-        closest_node
-    }
-
-    fn create_nodes(&mut self) {
-        // Create a number of nodes equal to the NUM_NODES
-        let mut locations: HashSet<(isize, isize)> = HashSet::new();
-        while self.nodes.len() < *NUM_NODES {
-            let (x, y) = (
-                ::rand::thread_rng().gen_range(0..GRID_SIZE) as isize,
-                ::rand::thread_rng().gen_range(0..GRID_SIZE) as isize,
-            );
-            // Only add the nodes if it is a unique node
-            if locations.insert((x, y)) {
-                self.add_node(Node {
-                    x,
-                    y,
-                    index: self.nodes.len(),
-                    ..Default::default()
-                });
-            }
-        }
-    }
-
-    fn connect_nodes(&mut self) {
-        // Connects the nodes via a minimum spanning tree
-        let mut unconnected_nodes: Vec<usize> = (0..self.nodes.len()).collect();
-        let mut visited_nodes: Vec<usize> = Vec::new();
-
-        // Add an arbitrary node to act as the starting node
-        visited_nodes.push(match unconnected_nodes.pop() {
-            Some(index) => index,
-            None => return,
-        });
-
-        while !unconnected_nodes.is_empty() {
-            // Loop through all the nodes and link whichever node is closer
-            let mut closest_distance = f32::INFINITY;
-            let mut current_closest_node_pair: Option<(usize, usize)> = None;
-
-            // Allows for more natural linking between nodes
-            visited_nodes.shuffle(&mut ::rand::thread_rng());
-            for node_index in &visited_nodes {
-                let closest_index = self.closest_node(&unconnected_nodes, node_index).unwrap();
-                let (closest_node, node) = (&self.nodes[closest_index], &self.nodes[*node_index]);
-
-                let distance = Self::distance_between_nodes(closest_node, node);
-
-                // Update the closest_distance if the current node is closer
-                if distance < closest_distance {
-                    closest_distance = distance;
-                    current_closest_node_pair = Some((*node_index, closest_index));
-                }
-            }
-
-            match current_closest_node_pair {
-                Some((visited_node, non_visited_node)) => {
-                    self.add_edge(visited_node, non_visited_node);
-                    visited_nodes.push(non_visited_node);
-                    unconnected_nodes.retain(|index| *index != non_visited_node);
-                }
-                None => return,
-            }
-        }
-    }
-
     fn add_player(&mut self, unpopulated_nodes: &mut Vec<usize>) {
         for (i, index) in unpopulated_nodes.iter().enumerate() {
             if self.nodes[*index].neighbors.len() == 1 {
@@ -294,9 +445,10 @@ impl Graph {
                 break;
             }
         }
-        if self.current_player_position == None {
+        if self.current_player_position.is_none() {
             self.current_player_position = Some(unpopulated_nodes.pop().unwrap())
         }
+        self.visited = HashSet::from([self.current_player_position.unwrap()]);
     }
     fn add_goal(&mut self, unpopulated_nodes: &mut Vec<usize>) {
         for (i, index) in unpopulated_nodes.iter().enumerate() {
@@ -306,22 +458,26 @@ impl Graph {
                 break;
             }
         }
-        if self.goal_position == None {
+        if self.goal_position.is_none() {
             self.goal_position = Some(unpopulated_nodes.pop().unwrap())
         }
     }
     fn add_enemies(&mut self, unpopulated_nodes: &mut Vec<usize>) {
-        let mut num_enemies = *NUM_ENEMIES;
+        let mut num_enemies = self.layout.num_enemies;
         if num_enemies > 0 {
             let mut path = self.get_path(
                 self.current_player_position.unwrap(),
                 self.goal_position.unwrap(),
             );
-            path.shuffle(&mut ::rand::thread_rng());
+            path.shuffle(&mut self.rng);
             for index in &path {
                 if unpopulated_nodes.contains(index) {
                     num_enemies -= 1;
-                    self.nodes[*index].value = Tile::Enemy(Enemy {});
+                    // So the enemy isn't placed over by another one
+                    unpopulated_nodes.retain(|i| i != index);
+                    self.nodes[*index].value = Tile::Enemy(Enemy {
+                        kind: EnemyKind::random(&mut self.rng),
+                    });
                     break;
                 }
             }
@@ -331,20 +487,23 @@ impl Graph {
             self.nodes[unpopulated_nodes
                 .pop()
                 .expect("Ran out of nodes in enemies")]
-            .value = Tile::Enemy(Enemy {});
+            .value = Tile::Enemy(Enemy {
+                kind: EnemyKind::random(&mut self.rng),
+            });
         }
     }
     fn add_treasure(&mut self, unpopulated_nodes: &mut Vec<usize>) {
-        let mut num_treasure = *NUM_TREASURE;
+        let mut num_treasure = self.layout.num_treasure;
         let mut indices_to_remove = Vec::new();
         for index in unpopulated_nodes.iter() {
+            // Enemies are placed after, so the nodes used here still have to be taken out
+            if num_treasure == 0 {
+                break;
+            }
             if self.nodes[*index].neighbors.len() == 1 {
                 indices_to_remove.insert(0, *index);
                 self.nodes[*index].value = Tile::Treasure;
                 num_treasure -= 1;
-                if num_treasure == 0 {
-                    return;
-                }
             }
         }
         unpopulated_nodes.retain(|i| !indices_to_remove.contains(i));
@@ -354,12 +513,17 @@ impl Graph {
     }
     fn populate_board(&mut self) {
         let mut unpopulated_nodes: Vec<usize> = (0..self.nodes.len()).collect();
-        unpopulated_nodes.shuffle(&mut ::rand::thread_rng());
+        unpopulated_nodes.shuffle(&mut self.rng);
 
         self.add_goal(&mut unpopulated_nodes);
         self.add_player(&mut unpopulated_nodes);
         self.add_treasure(&mut unpopulated_nodes);
         self.add_enemies(&mut unpopulated_nodes);
+        // Every level has a shop if there is room for one
+        if let Some(index) = unpopulated_nodes.pop() {
+            self.nodes[index].value = Tile::Shop;
+        }
+        self.shop_stock = shop_stock(&mut self.rng);
     }
 
     pub fn get_path(&self, start_node: usize, end_node: usize) -> Vec<usize> {
@@ -369,6 +533,10 @@ impl Graph {
         nodes_to_visit.push_back((start_node, start_node));
         let mut visited_nodes: Vec<bool> = vec![false; self.nodes.len()];
         while let Some((parent, node)) = nodes_to_visit.pop_front() {
+            // A node can be queued by several neighbours, the first one to reach it is closest
+            if visited_nodes[node] {
+                continue;
+            }
             visited_nodes[node] = true;
             parents[node] = Some(parent);
             if node == end_node {
@@ -392,15 +560,102 @@ impl Graph {
         path
     }
 
-    pub fn move_player(&mut self, index: usize) {
-        self.nodes[self.current_player_position.unwrap()].value = Tile::Empty;
+    // Every node the player can walk to from where they are, in index order
+    pub fn reachable_nodes(&self) -> Vec<usize> {
+        let Some(start) = self.current_player_position else {
+            return Vec::new();
+        };
+        let mut visited = vec![false; self.nodes.len()];
+        let mut nodes_to_visit = VecDeque::from([start]);
+        visited[start] = true;
+        while let Some(node) = nodes_to_visit.pop_front() {
+            for neighbor in &self.nodes[node].neighbors {
+                if !visited[*neighbor] {
+                    visited[*neighbor] = true;
+                    nodes_to_visit.push_back(*neighbor);
+                }
+            }
+        }
+        (0..self.nodes.len())
+            .filter(|index| visited[*index] && *index != start && self.revealed(*index))
+            .collect()
+    }
 
-        if index == self.goal_position.unwrap() && self.player_path.last() == None {
-            self.reload();
-            return;
+    // The label typed to choose each reachable node. They are all the same length so no label
+    // is the start of another
+    pub fn node_labels(&self) -> Vec<(usize, String)> {
+        let letters: Vec<char> = LABEL_LETTERS.chars().collect();
+        let reachable = self.reachable_nodes();
+        let mut label_length = 1;
+        while letters.len().pow(label_length) < reachable.len() {
+            label_length += 1;
         }
+        reachable
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                let label = (0..label_length)
+                    .rev()
+                    .map(|digit| letters[i / letters.len().pow(digit) % letters.len()])
+                    .collect();
+                (*node, label)
+            })
+            .collect()
+    }
+
+    // The closest of the candidates in the direction given, preferring ones straight ahead
+    fn node_towards(
+        &self,
+        from: usize,
+        (direction_x, direction_y): (isize, isize),
+        candidates: &[usize],
+    ) -> Option<usize> {
+        let start = &self.nodes[from];
+        candidates
+            .iter()
+            .filter_map(|index| {
+                let node = &self.nodes[*index];
+                let (x, y) = (node.x - start.x, node.y - start.y);
+                let ahead = x * direction_x + y * direction_y;
+                let across = (x * direction_y - y * direction_x).abs();
+                (ahead > 0).then_some((ahead + 2 * across, *index))
+            })
+            .min()
+            .map(|(_, index)| index)
+    }
+
+    // With fog of war only the nodes the player has been on and their neighbours can be seen,
+    // and the goal so the player knows where they are heading
+    pub fn revealed(&self, index: usize) -> bool {
+        !self.layout.fog_of_war
+            || self.goal_position == Some(index)
+            || self.visited.contains(&index)
+            || self.nodes[index]
+                .neighbors
+                .iter()
+                .any(|neighbor| self.visited.contains(neighbor))
+    }
+
+    // The fraction of this level's nodes the player has stood on
+    pub fn explored(&self) -> f32 {
+        self.visited.len() as f32 / self.nodes.len() as f32
+    }
 
+    pub fn move_player(&mut self, index: usize) {
+        let current = self.current_player_position.unwrap();
+        if self.nodes[current].value != Tile::Shop {
+            self.nodes[current].value = Tile::Empty;
+        }
         self.current_player_position = Some(index);
+        self.visited.insert(index);
+    }
+
+    // The kind of enemy on the node the player is standing on
+    pub fn current_enemy(&self) -> Option<EnemyKind> {
+        match &self.nodes[self.current_player_position?].value {
+            Tile::Enemy(enemy) => Some(enemy.kind),
+            _ => None,
+        }
     }
 
     pub fn distance(&self, index_1: usize, index_2: usize) -> f32 {
@@ -409,14 +664,23 @@ impl Graph {
         f32::sqrt(((node_1.x - node_2.x).pow(2) + (node_1.y - node_2.y).pow(2)) as f32)
     }
 
-    pub fn reload(&mut self) {
-        *self = Graph::new();
+    pub fn reload(&mut self, level: usize, layout: MapLayout) {
+        // Keep the seed and rng so the next level carries on from this one
+        self.layout = layout;
+        self.nodes = Vec::new();
+        self.current_player_position = None;
+        self.goal_position = None;
+        self.player_path = Vec::new();
+        self.generate(level);
     }
     fn draw_edges(&self) {
-        let y_scalar = screen_height() / GRID_SIZE as f32;
-        let x_scalar = screen_width() / GRID_SIZE as f32;
+        let y_scalar = virtual_height() / self.layout.grid_size as f32;
+        let x_scalar = virtual_width() / self.layout.grid_size as f32;
         for node in &self.nodes {
             for neighbor in &node.neighbors {
+                if !self.revealed(node.index) || !self.revealed(*neighbor) {
+                    continue;
+                }
                 draw_line(
                     node.x as f32 * x_scalar + NODE_SIZE / 2.0,
                     node.y as f32 * y_scalar + NODE_SIZE / 2.0,
@@ -430,7 +694,35 @@ impl Graph {
     }
     fn draw_thing(sprite: &Sprite, x: f32, y: f32) {
         let (texture, shrink_factor) = match sprite {
-            Sprite::Enemy => (*ENEMY_TEXTURE, ENEMY_SIZE / ENEMY_TEXTURE.width()),
+            Sprite::Enemy(kind) => {
+                let (width, height) = enemy_size(Foe::Enemy(*kind), ENEMY_SIZE);
+                draw_enemy(
+                    Foe::Enemy(*kind),
+                    x - width / 2.,
+                    y - height / 2.,
+                    width,
+                    false,
+                );
+                return;
+            }
+            Sprite::Boss(boss) => {
+                // Stand the boss next to the crown it is guarding
+                let foe = Foe::Boss {
+                    boss: *boss,
+                    phase: 0,
+                };
+                let (width, height) = enemy_size(foe, ENEMY_SIZE);
+                draw_enemy(foe, x - width, y - height, width, false);
+                return;
+            }
+            Sprite::Shop => {
+                // A gold coin, there is no shop texture
+                draw_circle(x, y, TREASURE_SIZE / 2.5, GOLD);
+                draw_circle_lines(x, y, TREASURE_SIZE / 2.5, 4., ORANGE);
+                let size = measure_text("$", None, 40, 1.);
+                draw_text("$", x - size.width / 2., y + size.height / 2., 40., BROWN);
+                return;
+            }
             Sprite::Player => (*PLAYER_TEXTURE, PLAYER_SIZE / PLAYER_TEXTURE.width()),
             Sprite::PlayerArmoured => (
                 *PLAYER_ARMOURED_TEXTURE,
@@ -456,12 +748,14 @@ impl Graph {
     }
 
     pub fn draw_terrain(&self, current_background: &usize) {
-        let texture = self.background_order[*current_background].map_texture;
-        let scalar = screen_width() / texture.width();
+        let texture = self.background_order[*current_background]
+            .background
+            .texture();
+        let scalar = virtual_width() / texture.width();
         draw_texture_ex(
             texture,
-            screen_width() / 2. - texture.width() * scalar / 2.,
-            screen_height() - texture.height() * scalar,
+            virtual_width() / 2. - texture.width() * scalar / 2.,
+            virtual_height() - texture.height() * scalar,
             WHITE,
             DrawTextureParams {
                 dest_size: Some(Vec2::from([
@@ -473,21 +767,109 @@ impl Graph {
         );
     }
 
-    pub fn draw_graph(&self, armoured: &bool, current_background: &usize) {
-        let y_scalar = screen_height() / GRID_SIZE as f32;
-        let x_scalar = screen_width() / GRID_SIZE as f32;
+    // Where the centre of a node is drawn on a screen of the given size
+    pub fn node_position(
+        &self,
+        index: usize,
+        (screen_width, screen_height): (f32, f32),
+    ) -> (f32, f32) {
+        let y_scalar = screen_height / self.layout.grid_size as f32;
+        let x_scalar = screen_width / self.layout.grid_size as f32;
+        let node = &self.nodes[index];
+        (
+            node.x as f32 * x_scalar + NODE_SIZE / 2.0,
+            node.y as f32 * y_scalar + NODE_SIZE / 2.0,
+        )
+    }
+
+    // Highlights the path to the node the keyboard cursor is on
+    fn draw_planned_path(&self, cursor: &MapCursor) {
+        let (Some(target), Some(position)) = (cursor.node, self.current_player_position) else {
+            return;
+        };
+        let screen_size = (virtual_width(), virtual_height());
+        let path = self.get_path(position, target);
+        for pair in path.windows(2) {
+            let (x_1, y_1) = self.node_position(pair[0], screen_size);
+            let (x_2, y_2) = self.node_position(pair[1], screen_size);
+            draw_line(
+                x_1,
+                y_1,
+                x_2,
+                y_2,
+                EDGE_SIZE,
+                Color::from_rgba(255, 215, 0, 200),
+            );
+        }
+        let (x, y) = self.node_position(target, screen_size);
+        draw_circle_lines(x, y, NODE_SIZE / 2. + 5., 6., GOLD);
+    }
+
+    // Shows the label to type next to each node, greying out the part already typed
+    fn draw_labels(&self, cursor: &MapCursor, word_travel: bool) {
+        let labels = if word_travel {
+            cursor.words.clone()
+        } else {
+            self.node_labels()
+        };
+        for (node, label) in labels {
+            let (x, y) = self.node_position(node, (virtual_width(), virtual_height()));
+            let (label_x, label_y) = (x + NODE_SIZE / 3., y - NODE_SIZE / 3.);
+            let size = measure_text(&label, None, LABEL_FONT_SIZE, 1.);
+            draw_rectangle(
+                label_x - 4.,
+                label_y - size.offset_y - 4.,
+                size.width + 8.,
+                size.height + 8.,
+                Color::from_rgba(255, 255, 255, 220),
+            );
+            let typed = if label.starts_with(&cursor.typed) {
+                cursor.typed.len()
+            } else {
+                0
+            };
+            draw_text(
+                &label[..typed],
+                label_x,
+                label_y,
+                f32::from(LABEL_FONT_SIZE),
+                GRAY,
+            );
+            draw_text(
+                &label[typed..],
+                label_x + measure_text(&label[..typed], None, LABEL_FONT_SIZE, 1.).width,
+                label_y,
+                f32::from(LABEL_FONT_SIZE),
+                BLACK,
+            );
+        }
+    }
+
+    pub fn draw_graph(
+        &self,
+        armoured: &bool,
+        current_background: &usize,
+        cursor: &MapCursor,
+        word_travel: bool,
+    ) {
+        let boss = self.background_order[*current_background].boss;
         self.draw_terrain(current_background);
         self.draw_edges();
+        self.draw_planned_path(cursor);
         for node in &self.nodes {
-            let base_x = node.x as f32 * x_scalar + NODE_SIZE / 2.0;
-            let base_y = node.y as f32 * y_scalar + NODE_SIZE / 2.0;
+            let (base_x, base_y) =
+                self.node_position(node.index, (virtual_width(), virtual_height()));
+            if !self.revealed(node.index) {
+                continue;
+            }
 
             Self::draw_thing(&Sprite::Node, base_x, base_y);
 
-            match node.value {
+            match &node.value {
                 Tile::Empty => (),
-                Tile::Enemy(_) => Self::draw_thing(&Sprite::Enemy, base_x, base_y),
+                Tile::Enemy(enemy) => Self::draw_thing(&Sprite::Enemy(enemy.kind), base_x, base_y),
                 Tile::Treasure => Self::draw_thing(&Sprite::Treasure, base_x, base_y),
+                Tile::Shop => Self::draw_thing(&Sprite::Shop, base_x, base_y),
             }
 
             if self.current_player_position.unwrap() == node.index {
@@ -500,26 +882,205 @@ impl Graph {
 
             if self.goal_position.unwrap() == node.index {
                 Self::draw_thing(&Sprite::Goal, base_x, base_y);
+                Self::draw_thing(&Sprite::Boss(boss), base_x, base_y);
             }
         }
+        self.draw_labels(cursor, word_travel);
+        draw_seed(self.seed, BLACK);
     }
 }
 
-pub fn mouse_events(graph: &mut Graph) {
-    if is_mouse_button_pressed(MouseButton::Left) {
-        let shrink_factor = NODE_SIZE / NODE_TEXTURE.width();
+// Drawn in the top right corner so a run can be replayed
+pub fn draw_seed(seed: u64, color: Color) {
+    let text = format!("Seed: {}", seed);
+    let font_size = 30;
+    draw_text_ex(
+        &text,
+        virtual_width() - measure_text(&text, None, font_size, 1.).width - 20.,
+        font_size as f32,
+        TextParams {
+            font_size,
+            font_scale: 1.0,
+            color,
+            font_scale_aspect: 1.0,
+            ..Default::default()
+        },
+    );
+}
 
-        let (mouse_x, mouse_y) = mouse_position();
-        let y_scalar = screen_height() / GRID_SIZE as f32;
-        let x_scalar = screen_width() / GRID_SIZE as f32;
+pub fn mouse_events(
+    graph: &mut Graph,
+    click: Option<(f32, f32)>,
+    (screen_width, screen_height): (f32, f32),
+) {
+    if let Some((mouse_x, mouse_y)) = click {
+        // Find the grid position whose node centre is closest to the click
+        let y_scalar = screen_height / graph.layout.grid_size as f32;
+        let x_scalar = screen_width / graph.layout.grid_size as f32;
         let (x, y) = (
-            ((mouse_x - NODE_SIZE / 2.0 + NODE_TEXTURE.width() * shrink_factor / 2.) / x_scalar)
-                .round() as isize,
-            ((mouse_y - NODE_SIZE / 2.0 + NODE_TEXTURE.height() * shrink_factor / 2.) / y_scalar)
-                .round() as isize,
+            ((mouse_x - NODE_SIZE / 2.0) / x_scalar).round() as isize,
+            ((mouse_y - NODE_SIZE / 2.0) / y_scalar).round() as isize,
         );
-        if let Some(end_node) = graph.get_node(x, y) {
+        if let Some(end_node) = graph.get_node(x, y).filter(|node| graph.revealed(*node)) {
             graph.player_path = graph.get_path(graph.current_player_position.unwrap(), end_node);
         }
     }
 }
+
+// Type a node's label or move to it with the arrow keys, then press enter to walk there
+pub fn keyboard_events(graph: &mut Graph, cursor: &mut MapCursor, input: &mut impl Input) {
+    let labels = graph.node_labels();
+    let reachable: Vec<usize> = labels.iter().map(|(node, _)| *node).collect();
+    // The map may have changed under the cursor since it was placed
+    if cursor.node.is_some_and(|node| !reachable.contains(&node)) {
+        cursor.node = None;
+    }
+    while let Some(c) = input.char_pressed() {
+        if !c.is_ascii_alphabetic() {
+            continue;
+        }
+        cursor.typed.push(c.to_ascii_lowercase());
+        if let Some((node, _)) = labels.iter().find(|(_, label)| *label == cursor.typed) {
+            cursor.node = Some(*node);
+            cursor.typed.clear();
+        } else if !labels
+            .iter()
+            .any(|(_, label)| label.starts_with(&cursor.typed))
+        {
+            cursor.typed.clear();
+        }
+    }
+    if input.key_pressed(KeyCode::Backspace) {
+        cursor.typed.pop();
+    }
+
+    let directions = [
+        (KeyCode::Left, (-1, 0)),
+        (KeyCode::Right, (1, 0)),
+        (KeyCode::Up, (0, -1)),
+        (KeyCode::Down, (0, 1)),
+    ];
+    for (key, direction) in directions {
+        if !input.key_pressed(key) {
+            continue;
+        }
+        if let Some(from) = cursor.node.or(graph.current_player_position) {
+            if let Some(next) = graph.node_towards(from, direction, &reachable) {
+                cursor.node = Some(next);
+            }
+        }
+    }
+
+    if input.key_pressed(KeyCode::Enter) {
+        if let Some(target) = cursor.node.take() {
+            graph.player_path = graph.get_path(graph.current_player_position.unwrap(), target);
+        }
+    }
+}
+
+// Type the word next to a neighbouring node to walk there, returns how many words were mistyped
+pub fn word_travel_events(
+    graph: &mut Graph,
+    cursor: &mut MapCursor,
+    corpus: &str,
+    input: &mut impl Input,
+) -> usize {
+    let position = graph.current_player_position.unwrap();
+    if cursor.words_at != Some(position) {
+        let mut neighbors = graph.nodes[position].neighbors.clone();
+        neighbors.sort_unstable();
+        neighbors.dedup();
+        let words = return_words(corpus, neighbors.len(), &mut graph.rng);
+        cursor.words = neighbors.into_iter().zip(words).collect();
+        cursor.words_at = Some(position);
+        cursor.typed.clear();
+        cursor.mistyped = false;
+    }
+    let mut mistakes = 0;
+    while let Some(c) = input.char_pressed() {
+        // A space starts the next word
+        if c.is_whitespace() {
+            cursor.typed.clear();
+            cursor.mistyped = false;
+            continue;
+        }
+        cursor.typed.push(c.to_ascii_lowercase());
+        if let Some((node, _)) = cursor.words.iter().find(|(_, word)| *word == cursor.typed) {
+            graph.player_path = graph.get_path(position, *node);
+            cursor.typed.clear();
+            cursor.mistyped = false;
+        } else if !cursor
+            .words
+            .iter()
+            .any(|(_, word)| word.starts_with(&cursor.typed))
+        {
+            // The rest of a word that has gone wrong costs nothing more
+            if !cursor.mistyped {
+                mistakes += 1;
+                cursor.mistyped = true;
+            }
+            cursor.typed.clear();
+        }
+    }
+    if input.key_pressed(KeyCode::Backspace) {
+        cursor.typed.pop();
+    }
+    mistakes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_seed_generates_the_same_dungeon() {
+        let (first, second) = (
+            Graph::from_seed(42, MapLayout::default()),
+            Graph::from_seed(42, MapLayout::default()),
+        );
+        assert_eq!(first.nodes, second.nodes);
+        assert_eq!(
+            first.current_player_position,
+            second.current_player_position
+        );
+        assert_eq!(first.goal_position, second.goal_position);
+    }
+
+    #[test]
+    fn every_level_connects_the_player_to_the_goal() {
+        for config in [
+            GameConfig::easy(),
+            GameConfig::default(),
+            GameConfig::hard(),
+        ] {
+            for seed in 0..50 {
+                let mut graph = Graph::from_seed(seed, MapLayout::new(&config));
+                for level in 0..graph.background_order.len() {
+                    graph.reload(level, MapLayout::new(&config));
+                    let (start, goal) = (
+                        graph.current_player_position.unwrap(),
+                        graph.goal_position.unwrap(),
+                    );
+                    assert_ne!(start, goal);
+                    let path = graph.get_path(start, goal);
+                    assert_eq!((path[0], path[path.len() - 1]), (goal, start));
+                    for pair in path.windows(2) {
+                        assert!(graph.nodes[pair[0]].neighbors.contains(&pair[1]));
+                    }
+                    let count = |tile: fn(&Tile) -> bool| {
+                        graph.nodes.iter().filter(|node| tile(&node.value)).count()
+                    };
+                    assert_eq!(
+                        count(|tile| matches!(tile, Tile::Enemy(_))),
+                        graph.layout.num_enemies
+                    );
+                    assert_eq!(
+                        count(|tile| *tile == Tile::Treasure),
+                        graph.layout.num_treasure
+                    );
+                    assert!(count(|tile| *tile == Tile::Shop) <= 1);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::platform::{virtual_height, virtual_width, Input};
+use macroquad::prelude::*;
+use std::time::Instant;
+
+const MENU_FONT_SIZE: u16 = 50;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PauseOption {
+    Resume,
+    Settings,
+    SaveAndQuit,
+    Abandon,
+}
+
+impl PauseOption {
+    pub const ALL: [PauseOption; 4] = [
+        PauseOption::Resume,
+        PauseOption::Settings,
+        PauseOption::SaveAndQuit,
+        PauseOption::Abandon,
+    ];
+
+    fn name(&self, in_run: bool) -> &'static str {
+        match self {
+            PauseOption::Resume => "Resume",
+            PauseOption::Settings => "Settings",
+            PauseOption::SaveAndQuit if in_run => "Save and Quit",
+            PauseOption::SaveAndQuit => "Quit",
+            PauseOption::Abandon if in_run => "Abandon Run",
+            PauseOption::Abandon => "Back to Title",
+        }
+    }
+}
+
+// Shown over whatever was happening when escape was pressed
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Pause {
+    pub selected: usize,
+    // Every timer is pushed back by how long the game was paused for
+    pub paused_at: Instant,
+    pub settings_open: bool,
+}
+
+impl Pause {
+    pub fn new(now: Instant) -> Self {
+        Pause {
+            selected: 0,
+            paused_at: now,
+            settings_open: false,
+        }
+    }
+}
+
+pub fn pause_menu_position(index: usize, (screen_width, screen_height): (f32, f32)) -> (f32, f32) {
+    (
+        screen_width / 2.,
+        screen_height / 2.5 + index as f32 * 1.6 * f32::from(MENU_FONT_SIZE),
+    )
+}
+
+pub fn draw_pause_menu(pause: &Pause, in_run: bool) {
+    draw_rectangle(
+        0.,
+        0.,
+        virtual_width(),
+        virtual_height(),
+        Color::from_rgba(0, 0, 0, 190),
+    );
+    let title = "Paused";
+    draw_text(
+        title,
+        virtual_width() / 2. - measure_text(title, None, 80, 1.).width / 2.,
+        virtual_height() / 4.,
+        80.,
+        WHITE,
+    );
+    for (i, option) in PauseOption::ALL.iter().enumerate() {
+        let (x, y) = pause_menu_position(i, (virtual_width(), virtual_height()));
+        let text = option.name(in_run);
+        draw_text(
+            text,
+            x - measure_text(text, None, MENU_FONT_SIZE, 1.).width / 2.,
+            y,
+            f32::from(MENU_FONT_SIZE),
+            if i == pause.selected { YELLOW } else { WHITE },
+        );
+    }
+}
+
+pub fn pause_select(input: &impl Input, selected: &mut usize) -> Option<PauseOption> {
+    let num_options = PauseOption::ALL.len();
+    if input.key_pressed(KeyCode::Down) {
+        *selected = (*selected + 1) % num_options;
+    }
+    if input.key_pressed(KeyCode::Up) {
+        *selected = (*selected + num_options - 1) % num_options;
+    }
+    if input.key_pressed(KeyCode::Enter) {
+        return Some(PauseOption::ALL[*selected]);
+    }
+    // Pressing escape again carries on
+    if input.key_pressed(KeyCode::Escape) {
+        return Some(PauseOption::Resume);
+    }
+    if let Some((_, mouse_y)) = input.mouse_clicked() {
+        for (i, option) in PauseOption::ALL.iter().enumerate() {
+            let (_, y) = pause_menu_position(i, input.screen_size());
+            if mouse_y <= y && mouse_y >= y - f32::from(MENU_FONT_SIZE) {
+                *selected = i;
+                return Some(*option);
+            }
+        }
+    }
+    None
+}
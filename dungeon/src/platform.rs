@@ -0,0 +1,291 @@
+use crate::combat::{
+    animation_finished, draw_combat, draw_combat_animation, draw_combat_background,
+};
+use crate::config::draw_settings_menu;
+use crate::editor::draw_editor;
+use crate::end::{draw_death_screen, draw_level_summary, draw_victory_screen, run_score};
+use crate::pause::draw_pause_menu;
+use crate::profile::draw_profile;
+use crate::relics::draw_relics;
+use crate::sentences::draw_corpus_menu;
+use crate::shop::{draw_gold, draw_shop};
+use crate::start::{draw_practice, draw_title_screen};
+use crate::stats::draw_combat_summary;
+use crate::treasure::{draw_card_key, draw_reward_buttons, offered_cards, reward_layout};
+use crate::{EndCondition, Game, GameState, RewardType, REROLL_PRICE};
+use macroquad::prelude::*;
+use std::time::Instant;
+
+// Everything is laid out on a virtual screen this many pixels tall and as wide as the
+// window's shape allows, which is then scaled to fit the window
+pub const VIRTUAL_HEIGHT: f32 = 1080.;
+// Windows narrower or wider than this get black bars at the sides or top and bottom
+const MIN_ASPECT_RATIO: f32 = 16. / 10.;
+const MAX_ASPECT_RATIO: f32 = 21. / 9.;
+
+// The size of the virtual screen for a window of the given size
+pub fn virtual_size((window_width, window_height): (f32, f32)) -> (f32, f32) {
+    let aspect_ratio = (window_width / window_height).clamp(MIN_ASPECT_RATIO, MAX_ASPECT_RATIO);
+    (VIRTUAL_HEIGHT * aspect_ratio, VIRTUAL_HEIGHT)
+}
+
+pub fn virtual_width() -> f32 {
+    virtual_size((screen_width(), screen_height())).0
+}
+
+pub fn virtual_height() -> f32 {
+    VIRTUAL_HEIGHT
+}
+
+// The part of the window the virtual screen is drawn to as (x, y, width, height)
+fn viewport(window_size: (f32, f32)) -> (f32, f32, f32, f32) {
+    let (window_width, window_height) = window_size;
+    let (virtual_width, virtual_height) = virtual_size(window_size);
+    let scale = (window_width / virtual_width).min(window_height / virtual_height);
+    let (width, height) = (virtual_width * scale, virtual_height * scale);
+    (
+        (window_width - width) / 2.,
+        (window_height - height) / 2.,
+        width,
+        height,
+    )
+}
+
+// Makes everything drawn afterwards use virtual pixels
+pub fn set_virtual_camera() {
+    let window_size = (screen_width(), screen_height());
+    let (x, y, width, height) = viewport(window_size);
+    let (virtual_width, virtual_height) = virtual_size(window_size);
+    set_camera(&Camera2D {
+        viewport: Some((x as i32, y as i32, width as i32, height as i32)),
+        ..Camera2D::from_display_rect(Rect::new(0., 0., virtual_width, virtual_height))
+    });
+}
+
+// Converts a position in the window, like the mouse, to virtual pixels
+pub fn window_to_virtual((window_x, window_y): (f32, f32), window_size: (f32, f32)) -> (f32, f32) {
+    let (x, y, width, height) = viewport(window_size);
+    let (virtual_width, virtual_height) = virtual_size(window_size);
+    (
+        (window_x - x) * virtual_width / width,
+        (window_y - y) * virtual_height / height,
+    )
+}
+
+// Where the game logic gets its input from, a window or a scripted test
+pub trait Input {
+    fn char_pressed(&mut self) -> Option<char>;
+    fn key_pressed(&self, key: KeyCode) -> bool;
+    fn key_down(&self, key: KeyCode) -> bool;
+    fn key_released(&self, key: KeyCode) -> bool;
+    // The position of a left click made this frame
+    fn mouse_clicked(&self) -> Option<(f32, f32)>;
+    // Where the left button was let go this frame
+    fn mouse_released(&self) -> Option<(f32, f32)>;
+    fn screen_size(&self) -> (f32, f32);
+}
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+pub(crate) trait Renderer {
+    fn draw(&mut self, game: &Game, now: Instant);
+}
+
+pub struct MacroquadInput;
+
+impl Input for MacroquadInput {
+    fn char_pressed(&mut self) -> Option<char> {
+        get_char_pressed()
+    }
+
+    fn key_pressed(&self, key: KeyCode) -> bool {
+        is_key_pressed(key)
+    }
+
+    fn key_down(&self, key: KeyCode) -> bool {
+        is_key_down(key)
+    }
+
+    fn key_released(&self, key: KeyCode) -> bool {
+        is_key_released(key)
+    }
+
+    fn mouse_clicked(&self) -> Option<(f32, f32)> {
+        if is_mouse_button_pressed(MouseButton::Left) {
+            Some(window_to_virtual(
+                mouse_position(),
+                (screen_width(), screen_height()),
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn mouse_released(&self) -> Option<(f32, f32)> {
+        if is_mouse_button_released(MouseButton::Left) {
+            Some(window_to_virtual(
+                mouse_position(),
+                (screen_width(), screen_height()),
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn screen_size(&self) -> (f32, f32) {
+        (virtual_width(), virtual_height())
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+pub struct MacroquadRenderer;
+
+impl Renderer for MacroquadRenderer {
+    fn draw(&mut self, game: &Game, now: Instant) {
+        let (graph, player, variables) = (&game.graph, &game.player, &game.variables);
+        clear_background(BLACK);
+        set_virtual_camera();
+        draw_rectangle(0., 0., virtual_width(), virtual_height(), WHITE);
+        match game.state {
+            GameState::LoadTextures | GameState::Quit => (),
+            GameState::Title => draw_title_screen(variables.selected_title_option, game.save_found),
+            GameState::Practice => {
+                if let Some(sentence) = &variables.sentence {
+                    draw_practice(
+                        sentence,
+                        &player.sentence,
+                        variables.practice_stats.as_ref(),
+                    );
+                }
+            }
+            GameState::Statistics => draw_profile(&game.profile),
+            GameState::Settings => draw_settings_menu(&game.settings),
+            GameState::Editor => draw_editor(
+                &game.editor,
+                window_to_virtual(mouse_position(), (screen_width(), screen_height())),
+            ),
+            GameState::SelectCorpus => draw_corpus_menu(variables.selected_corpus),
+            GameState::MainMap => {
+                graph.draw_graph(
+                    &player.armoured,
+                    &variables.current_background,
+                    &variables.map_cursor,
+                    game.settings.word_travel,
+                );
+                draw_gold(variables.gold);
+                draw_relics(&variables.relics);
+            }
+            GameState::Shop => {
+                graph.draw_graph(
+                    &player.armoured,
+                    &variables.current_background,
+                    &variables.map_cursor,
+                    game.settings.word_travel,
+                );
+                draw_gold(variables.gold);
+                draw_relics(&variables.relics);
+                draw_shop(&graph.shop_stock, variables.gold, &variables.relics);
+            }
+            GameState::EnterCombat | GameState::ExitCombat => {
+                if !animation_finished(variables.entered_combat, now) {
+                    draw_combat_animation();
+                }
+            }
+            GameState::Combat => {
+                let level_info = &graph.background_order[variables.current_background];
+                if let Some(sentence) = &variables.sentence {
+                    draw_combat(
+                        sentence,
+                        player,
+                        game.foe(),
+                        variables.enemy_health,
+                        variables.streak,
+                        level_info,
+                        &variables.consumables.slots(now),
+                    );
+                    draw_relics(&variables.relics);
+                }
+            }
+            GameState::CombatSummary => {
+                let level_info = &graph.background_order[variables.current_background];
+                draw_combat_background(&level_info.sky_color, &level_info.ground_color);
+                if let Some(stats) = variables.combat_stats.last() {
+                    draw_combat_summary(stats);
+                }
+            }
+            GameState::Rewarded(reward_type) => {
+                graph.draw_graph(
+                    &player.armoured,
+                    &variables.current_background,
+                    &variables.map_cursor,
+                    game.settings.word_travel,
+                );
+                draw_gold(variables.gold);
+                draw_relics(&variables.relics);
+                let cards = offered_cards(&variables.reward_offer);
+                let layout = reward_layout(&cards, (virtual_width(), virtual_height()));
+                for (i, (card, (x, y))) in layout.iter().enumerate() {
+                    card.draw_card(*x, *y);
+                    draw_card_key(i, *x, *y);
+                }
+                draw_reward_buttons(REROLL_PRICE, variables.gold);
+                if let (RewardType::EndOfLevel, Some(explored)) =
+                    (reward_type, variables.explored.last())
+                {
+                    draw_level_summary(variables.explored.len(), *explored);
+                }
+            }
+            GameState::EndOfGame(EndCondition::Death) => draw_death_screen(
+                &(variables.current_background + 1),
+                &variables.num_enemies_defeated,
+                &graph.seed,
+                &variables.combat_stats,
+                run_score(variables.num_enemies_defeated, &variables.explored),
+                &variables.relics,
+            ),
+            GameState::EndOfGame(EndCondition::Success) => draw_victory_screen(
+                &variables.num_enemies_defeated,
+                &graph.seed,
+                &variables.combat_stats,
+                run_score(variables.num_enemies_defeated, &variables.explored),
+                &variables.relics,
+            ),
+        }
+        if let Some(pause) = &game.pause {
+            if pause.settings_open {
+                draw_settings_menu(&game.settings);
+            } else {
+                draw_pause_menu(pause, game.state.in_run());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn any_window_shows_the_whole_virtual_screen() {
+        // A 16:9 window fills the screen, squarer or wider ones get bars
+        assert_eq!(virtual_size((1280., 720.)), (1920., 1080.));
+        for window in [(1280., 720.), (1000., 1000.), (4000., 1000.), (800., 600.)] {
+            let (width, height) = virtual_size(window);
+            assert!(width >= 1600. && height == 1080.);
+            let centre = window_to_virtual((window.0 / 2., window.1 / 2.), window);
+            assert!((centre.0 - width / 2.).abs() < 0.01);
+            assert!((centre.1 - height / 2.).abs() < 0.01);
+        }
+        let (x, y) = window_to_virtual((0., 500. - 1000. / 1.6 / 2.), (1000., 1000.));
+        assert!(x.abs() < 0.01 && y.abs() < 0.01);
+    }
+}
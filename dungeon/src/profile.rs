@@ -0,0 +1,268 @@
+use crate::end::run_score;
+use crate::platform::{virtual_height, virtual_width};
+use crate::stats::{character_name, CombatStats};
+use crate::{EndCondition, Game};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const PROFILE_FILE: &str = "profile.json";
+const CHARTED_RUNS: usize = 20;
+const NUM_MISSED: usize = 10;
+const FONT_SIZE: f32 = 30.;
+
+// Every run the player has finished, kept between sessions
+#[derive(Serialize, Deserialize, Default)]
+pub struct Profile {
+    pub runs: Vec<RunRecord>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RunRecord {
+    // Seconds since the unix epoch
+    pub finished_at: u64,
+    pub seed: u64,
+    pub levels_reached: usize,
+    pub num_enemies_defeated: usize,
+    // None if the run was won
+    pub cause_of_death: Option<String>,
+    pub combats: Vec<CombatStats>,
+    #[serde(default)]
+    pub score: usize,
+}
+
+// What can take the player's health
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DamageSource {
+    Foe,
+    // Mistyping a word when travelling by words
+    WordTravel,
+}
+
+impl RunRecord {
+    fn average(&self, value: fn(&CombatStats) -> f32) -> Option<f32> {
+        if self.combats.is_empty() {
+            return None;
+        }
+        Some(self.combats.iter().map(value).sum::<f32>() / self.combats.len() as f32)
+    }
+}
+
+impl Profile {
+    pub fn load(data_dir: &Path) -> Profile {
+        match fs::read_to_string(data_dir.join(PROFILE_FILE)) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|error| {
+                println!("Failed to read the profile: {}", error);
+                Profile::default()
+            }),
+            Err(_) => Profile::default(),
+        }
+    }
+
+    fn save(&self, data_dir: &Path) {
+        match serde_json::to_string(self) {
+            Ok(contents) => {
+                if let Err(error) = fs::write(data_dir.join(PROFILE_FILE), contents) {
+                    println!("Failed to write the profile: {}", error);
+                }
+            }
+            Err(error) => println!("Failed to serialize the profile: {}", error),
+        }
+    }
+
+    // Adds up the mistakes of every combat ever fought, most missed first
+    fn most_missed<K: Ord + Clone>(
+        &self,
+        errors: fn(&CombatStats) -> &BTreeMap<K, usize>,
+    ) -> Vec<(K, usize)> {
+        let mut totals: BTreeMap<K, usize> = BTreeMap::new();
+        for combat in self.runs.iter().flat_map(|run| &run.combats) {
+            for (key, count) in errors(combat) {
+                *totals.entry(key.clone()).or_default() += count;
+            }
+        }
+        let mut totals: Vec<(K, usize)> = totals.into_iter().collect();
+        totals.sort_by_key(|(_, count)| Reverse(*count));
+        totals.truncate(NUM_MISSED);
+        totals
+    }
+}
+
+pub(crate) fn record_run(game: &mut Game, end_condition: EndCondition) {
+    let variables = &game.variables;
+    let levels_reached = (variables.current_background + 1).min(game.graph.background_order.len());
+    game.profile.runs.push(RunRecord {
+        finished_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default(),
+        seed: game.graph.seed,
+        levels_reached,
+        num_enemies_defeated: variables.num_enemies_defeated,
+        cause_of_death: match end_condition {
+            EndCondition::Death => Some(match variables.last_hurt_by {
+                Some(DamageSource::WordTravel) => {
+                    format!("Lost their way on level {}", levels_reached)
+                }
+                _ => format!(
+                    "Defeated by a {} on level {}",
+                    game.foe().stats().name,
+                    levels_reached
+                ),
+            }),
+            EndCondition::Success => None,
+        },
+        combats: variables.combat_stats.clone(),
+        score: run_score(variables.num_enemies_defeated, &variables.explored),
+    });
+    game.profile.save(&game.data_dir);
+}
+
+// Runs without a value are left as gaps in the line
+fn draw_chart(title: &str, series: &[(Vec<Option<f32>>, Color)], max_value: f32, rect: Rect) {
+    draw_text(title, rect.x, rect.y - 10., FONT_SIZE, WHITE);
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2., GRAY);
+    draw_text(
+        &format!("{:.0}", max_value),
+        rect.x - 50.,
+        rect.y + FONT_SIZE / 2.,
+        FONT_SIZE,
+        GRAY,
+    );
+    draw_text("0", rect.x - 30., rect.y + rect.h, FONT_SIZE, GRAY);
+    for (values, color) in series {
+        let point = |i: usize| {
+            values[i].map(|value| {
+                (
+                    rect.x + rect.w * (i as f32 + 0.5) / CHARTED_RUNS as f32,
+                    rect.y + rect.h * (1. - value / max_value),
+                )
+            })
+        };
+        for i in 0..values.len() {
+            let Some((x, y)) = point(i) else {
+                continue;
+            };
+            draw_circle(x, y, 5., *color);
+            if let Some((previous_x, previous_y)) = i.checked_sub(1).and_then(point) {
+                draw_line(previous_x, previous_y, x, y, 3., *color);
+            }
+        }
+    }
+}
+
+fn draw_list(title: &str, lines: &[String], x: f32, y: f32) {
+    draw_text(title, x, y, FONT_SIZE, WHITE);
+    if lines.is_empty() {
+        draw_text("Nothing yet", x, y + FONT_SIZE, FONT_SIZE, GRAY);
+    }
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            line,
+            x,
+            y + FONT_SIZE * (i + 1) as f32,
+            FONT_SIZE,
+            LIGHTGRAY,
+        );
+    }
+}
+
+pub fn draw_profile(profile: &Profile) {
+    clear_background(BLACK);
+    let title = "Typing progress";
+    draw_text(
+        title,
+        virtual_width() / 2. - measure_text(title, None, 50, 1.).width / 2.,
+        60.,
+        50.,
+        WHITE,
+    );
+
+    let runs = &profile.runs;
+    let num_wins = runs
+        .iter()
+        .filter(|run| run.cause_of_death.is_none())
+        .count();
+    let best_wpm = runs
+        .iter()
+        .flat_map(|run| &run.combats)
+        .map(|combat| combat.wpm)
+        .fold(0., f32::max);
+    let summary = format!(
+        "{} runs, {} won, {} enemies defeated, best speed {:.1} wpm, best score {}",
+        runs.len(),
+        num_wins,
+        runs.iter()
+            .map(|run| run.num_enemies_defeated)
+            .sum::<usize>(),
+        best_wpm,
+        runs.iter().map(|run| run.score).max().unwrap_or_default()
+    );
+    draw_text(&summary, 80., 120., FONT_SIZE, LIGHTGRAY);
+
+    // Chart the most recent runs, oldest on the left
+    let recent = &runs[runs.len().saturating_sub(CHARTED_RUNS)..];
+    // One point per run, runs without a combat are gaps
+    let averages = |value: fn(&CombatStats) -> f32| -> Vec<Option<f32>> {
+        recent.iter().map(|run| run.average(value)).collect()
+    };
+    let wpm = averages(|combat| combat.wpm);
+    let chart_width = virtual_width() * 0.55;
+    let chart_height = (virtual_height() - 300.) / 2. - 60.;
+    draw_chart(
+        "Average words per minute",
+        &[(wpm.clone(), YELLOW)],
+        wpm.iter()
+            .flatten()
+            .fold(10., |max: f32, value| max.max(*value))
+            .ceil(),
+        Rect::new(100., 200., chart_width, chart_height),
+    );
+    draw_chart(
+        "Accuracy % (raw in red, corrected in green)",
+        &[
+            (
+                averages(|combat| combat.raw_accuracy * 100.),
+                Color::from_rgba(182, 0, 0, 255),
+            ),
+            (
+                averages(|combat| combat.corrected_accuracy * 100.),
+                Color::from_rgba(0, 182, 0, 255),
+            ),
+        ],
+        100.,
+        Rect::new(100., 300. + chart_height, chart_width, chart_height),
+    );
+
+    let list_x = 200. + chart_width;
+    let missed_keys: Vec<String> = profile
+        .most_missed(|combat| &combat.char_errors)
+        .iter()
+        .map(|(c, count)| format!("{} x{}", character_name(*c), count))
+        .collect();
+    draw_list("Most missed keys", &missed_keys, list_x, 200.);
+    let missed_bigrams: Vec<String> = profile
+        .most_missed(|combat| &combat.bigram_errors)
+        .iter()
+        .map(|(bigram, count)| format!("'{}' x{}", bigram.replace(' ', "⊔"), count))
+        .collect();
+    draw_list(
+        "Most missed pairs",
+        &missed_bigrams,
+        list_x,
+        260. + FONT_SIZE * NUM_MISSED as f32,
+    );
+
+    let prompt = "Press escape to go back";
+    draw_text(
+        prompt,
+        virtual_width() / 2. - measure_text(prompt, None, FONT_SIZE as u16, 1.).width / 2.,
+        virtual_height() - 40.,
+        FONT_SIZE,
+        GRAY,
+    );
+}
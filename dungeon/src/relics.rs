@@ -0,0 +1,175 @@
+use crate::platform::virtual_width;
+use ::rand::seq::SliceRandom;
+use macroquad::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const ICON_RADIUS: f32 = 22.;
+// Just under the gold in the top right corner
+const ICONS_Y: f32 = 110.;
+
+// Passive items that last for the whole run and react to what happens in combat
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Relic {
+    CoralCharm,
+    LuckyPebble,
+    SeaUrchin,
+    Whetstone,
+    PatienceStone,
+    TideClock,
+}
+
+// The moments in combat that relics can react to
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CombatEvent {
+    // A key typed correctly
+    Keystroke,
+    WordComplete,
+    Mistake,
+    HitTaken,
+    CombatEnd,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RelicAction {
+    Heal(f32),
+    Gold(usize),
+    // A fraction of the enemy's full health
+    DamageEnemy(f32),
+    // Pushes the enemy's next attack back
+    DelayAttack(Duration),
+}
+
+impl Relic {
+    pub const ALL: [Relic; 6] = [
+        Relic::CoralCharm,
+        Relic::LuckyPebble,
+        Relic::SeaUrchin,
+        Relic::Whetstone,
+        Relic::PatienceStone,
+        Relic::TideClock,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Relic::CoralCharm => "Coral Charm",
+            Relic::LuckyPebble => "Lucky Pebble",
+            Relic::SeaUrchin => "Sea Urchin",
+            Relic::Whetstone => "Whetstone",
+            Relic::PatienceStone => "Patience Stone",
+            Relic::TideClock => "Tide Clock",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Relic::CoralCharm => "Restore 5 health after every brawl",
+            Relic::LuckyPebble => "Earn 1 gold for every word you complete",
+            Relic::SeaUrchin => "Enemies lose 5% of their health whenever they hit you",
+            Relic::Whetstone => "Every correct key does 0.5% of the enemy's health in damage",
+            Relic::PatienceStone => "Every mistake delays the enemy's next attack by 0.3 seconds",
+            Relic::TideClock => {
+                "Every word you complete delays the enemy's next attack by 0.2 seconds"
+            }
+        }
+    }
+
+    pub fn reaction(&self, event: CombatEvent) -> Option<RelicAction> {
+        match (self, event) {
+            (Relic::CoralCharm, CombatEvent::CombatEnd) => Some(RelicAction::Heal(5.)),
+            (Relic::LuckyPebble, CombatEvent::WordComplete) => Some(RelicAction::Gold(1)),
+            (Relic::SeaUrchin, CombatEvent::HitTaken) => Some(RelicAction::DamageEnemy(0.05)),
+            (Relic::Whetstone, CombatEvent::Keystroke) => Some(RelicAction::DamageEnemy(0.005)),
+            (Relic::PatienceStone, CombatEvent::Mistake) => {
+                Some(RelicAction::DelayAttack(Duration::from_millis(300)))
+            }
+            (Relic::TideClock, CombatEvent::WordComplete) => {
+                Some(RelicAction::DelayAttack(Duration::from_millis(200)))
+            }
+            _ => None,
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            Relic::CoralCharm => Color::from_rgba(255, 110, 90, 255),
+            Relic::LuckyPebble => Color::from_rgba(120, 170, 90, 255),
+            Relic::SeaUrchin => Color::from_rgba(110, 40, 140, 255),
+            Relic::Whetstone => Color::from_rgba(130, 130, 140, 255),
+            Relic::PatienceStone => Color::from_rgba(60, 120, 200, 255),
+            Relic::TideClock => Color::from_rgba(20, 160, 160, 255),
+        }
+    }
+
+    // The initials of the relic's name
+    fn abbreviation(&self) -> String {
+        self.name()
+            .split(' ')
+            .filter_map(|word| word.chars().next())
+            .collect()
+    }
+}
+
+// Everything the relics do in response to the events, in the order the relics were found
+pub fn relic_actions(relics: &[Relic], events: &[CombatEvent]) -> Vec<RelicAction> {
+    events
+        .iter()
+        .flat_map(|event| relics.iter().filter_map(|relic| relic.reaction(*event)))
+        .collect()
+}
+
+pub fn random_relic(rng: &mut ChaCha8Rng) -> Relic {
+    *Relic::ALL.choose(rng).unwrap()
+}
+
+// A row of icons ending at the right edge of the screen
+pub fn draw_relics(relics: &[Relic]) {
+    let y = ICONS_Y;
+    let spacing = ICON_RADIUS * 2.5;
+    let right = virtual_width() - ICON_RADIUS - 20.;
+    for (i, relic) in relics.iter().rev().enumerate() {
+        let x = right - i as f32 * spacing;
+        draw_circle(x, y, ICON_RADIUS, relic.color());
+        draw_circle_lines(x, y, ICON_RADIUS, 3., BLACK);
+        let text = relic.abbreviation();
+        let size = measure_text(&text, None, 26, 1.);
+        draw_text(&text, x - size.width / 2., y + size.height / 2., 26., WHITE);
+    }
+}
+
+// Lists the relics found this run, for the end screens
+pub fn draw_relic_list(relics: &[Relic], y: f32) {
+    if relics.is_empty() {
+        return;
+    }
+    let names: Vec<&str> = relics.iter().map(|relic| relic.name()).collect();
+    let text = format!("Relics: {}", names.join(", "));
+    draw_text(
+        &text,
+        virtual_width() / 2. - measure_text(&text, None, 30, 1.).width / 2.,
+        y,
+        30.,
+        GOLD,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relics_react_to_combat_events() {
+        assert_eq!(
+            relic_actions(
+                &[Relic::CoralCharm, Relic::LuckyPebble],
+                &[CombatEvent::WordComplete, CombatEvent::CombatEnd]
+            ),
+            vec![RelicAction::Gold(1), RelicAction::Heal(5.)]
+        );
+        assert_eq!(
+            relic_actions(&[Relic::SeaUrchin], &[CombatEvent::WordComplete]),
+            vec![]
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::combat::Player;
+use crate::map::Graph;
+use crate::{Game, GameState, RewardType, Variables};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+const SAVE_FILE: &str = "save.json";
+// New fields are added with #[serde(default)] so older saves still load, bump this when a
+// change can't be read that way so old saves are ignored
+const SAVE_VERSION: u32 = 2;
+
+// The states a run can be resumed in, a fight that isn't over yet is restarted
+#[derive(Serialize, Deserialize, Copy, Clone)]
+enum SavedState {
+    MainMap,
+    EnterCombat,
+    // A fight that was won
+    ExitCombat,
+    Rewarded(RewardType),
+}
+
+#[derive(Serialize)]
+struct SaveFile<'a> {
+    version: u32,
+    state: SavedState,
+    graph: &'a Graph,
+    player: &'a Player,
+    variables: &'a Variables,
+}
+
+#[derive(Deserialize)]
+struct LoadedSaveFile {
+    version: u32,
+    state: SavedState,
+    graph: Graph,
+    player: Player,
+    variables: Variables,
+}
+
+pub fn save_exists(data_dir: &Path) -> bool {
+    data_dir.join(SAVE_FILE).exists()
+}
+
+pub fn delete_save(data_dir: &Path) {
+    if save_exists(data_dir) {
+        if let Err(error) = fs::remove_file(data_dir.join(SAVE_FILE)) {
+            println!("Failed to delete the save file: {}", error);
+        }
+    }
+}
+
+pub(crate) fn autosave(game: &Game) {
+    // A finished run can't be continued
+    if let GameState::EndOfGame(_) = game.state {
+        delete_save(&game.data_dir);
+        return;
+    }
+    if let Some(contents) = save_contents(game) {
+        if let Err(error) = fs::write(game.data_dir.join(SAVE_FILE), contents) {
+            println!("Failed to write the save file: {}", error);
+        }
+    }
+}
+
+// The save file for the run in progress, if there is one
+pub(crate) fn save_contents(game: &Game) -> Option<String> {
+    let state = match game.state {
+        GameState::LoadTextures
+        | GameState::Title
+        | GameState::Settings
+        | GameState::Practice
+        | GameState::Statistics
+        | GameState::Editor
+        | GameState::Quit
+        | GameState::SelectCorpus
+        | GameState::EndOfGame(_) => return None,
+        // The shop can be walked back onto
+        GameState::MainMap | GameState::Shop => SavedState::MainMap,
+        GameState::EnterCombat | GameState::Combat => SavedState::EnterCombat,
+        // The rewards have already been paid out, so the fight is left rather than fought again
+        GameState::CombatSummary | GameState::ExitCombat => SavedState::ExitCombat,
+        GameState::Rewarded(reward_type) => SavedState::Rewarded(reward_type),
+    };
+    let save_file = SaveFile {
+        version: SAVE_VERSION,
+        state,
+        graph: &game.graph,
+        player: &game.player,
+        variables: &game.variables,
+    };
+    match serde_json::to_string(&save_file) {
+        Ok(contents) => Some(contents),
+        Err(error) => {
+            println!("Failed to serialize the game: {}", error);
+            None
+        }
+    }
+}
+
+pub(crate) fn load_game(data_dir: &Path) -> Option<Game> {
+    game_from_save(&fs::read_to_string(data_dir.join(SAVE_FILE)).ok()?)
+}
+
+pub(crate) fn game_from_save(contents: &str) -> Option<Game> {
+    let save_file: LoadedSaveFile = match serde_json::from_str(contents) {
+        Ok(save_file) => save_file,
+        Err(error) => {
+            println!("Failed to read the save file: {}", error);
+            return None;
+        }
+    };
+    if save_file.version != SAVE_VERSION {
+        println!(
+            "Ignoring save file from version {} (expected {})",
+            save_file.version, SAVE_VERSION
+        );
+        return None;
+    }
+    let mut variables = save_file.variables;
+    let state = match save_file.state {
+        SavedState::MainMap => GameState::MainMap,
+        SavedState::EnterCombat => {
+            variables.entered_combat = Some(Instant::now());
+            GameState::EnterCombat
+        }
+        SavedState::ExitCombat => {
+            variables.entered_combat = Some(Instant::now());
+            GameState::ExitCombat
+        }
+        SavedState::Rewarded(reward_type) => GameState::Rewarded(reward_type),
+    };
+    let mut graph = save_file.graph;
+    if let Some(level) = &graph.custom_level {
+        graph.background_order = vec![level.level_info()];
+    }
+    Some(Game {
+        state,
+        player: save_file.player,
+        variables,
+        save_found: true,
+        ..Game::new(graph)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapLayout;
+
+    #[test]
+    fn saves_from_before_newer_fields_still_load() {
+        let mut game = Game::new(Graph::from_seed(3, MapLayout::default()));
+        game.state = GameState::MainMap;
+        let mut save: serde_json::Value =
+            serde_json::from_str(&save_contents(&game).unwrap()).unwrap();
+        for field in [
+            "card_effects",
+            "gold",
+            "relics",
+            "consumables",
+            "reward_offer",
+        ] {
+            save["variables"].as_object_mut().unwrap().remove(field);
+        }
+        for field in ["layout", "visited", "shop_stock", "custom_level"] {
+            save["graph"].as_object_mut().unwrap().remove(field);
+        }
+        let game = game_from_save(&save.to_string()).unwrap();
+        assert_eq!(game.state, GameState::MainMap);
+        assert_eq!(game.variables.gold, 0);
+        assert!(game.graph.shop_stock.is_empty());
+    }
+}
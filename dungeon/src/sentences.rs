@@ -1,6 +1,18 @@
+use crate::platform::{virtual_height, virtual_width, Input};
+use ::rand::{seq::SliceRandom, Rng};
 use lazy_static::{initialize, lazy_static};
-use rand::seq::SliceRandom;
+use macroquad::prelude::*;
+use serde::Deserialize;
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// Plain text (one sentence per line) and JSON corpus packs are read from here
+const CORPUS_DIRECTORY: &str = "corpora";
+// Lines of a .txt pack starting with this are comments, # would catch Rust attributes
+const CORPUS_COMMENT: &str = "//!";
+pub const BUILT_IN_CORPUS: &str = "Built-in";
+const MENU_FONT_SIZE: u16 = 40;
 
 lazy_static! {
     pub static ref SENTENCES: HashMap<usize, Vec<String>> =  HashMap::from([
@@ -848,14 +860,311 @@ lazy_static! {
     ]);
 }
 
+lazy_static! {
+    pub static ref CORPORA: Vec<Corpus> = load_corpora();
+}
+
+pub struct Corpus {
+    pub name: String,
+    pub sentences: HashMap<usize, Vec<String>>,
+}
+
+// A JSON pack is either a plain list of sentences or a named list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CorpusFile {
+    Sentences(Vec<String>),
+    Named {
+        name: String,
+        sentences: Vec<String>,
+    },
+}
+
+fn bucket_sentences(sentences: Vec<String>) -> HashMap<usize, Vec<String>> {
+    let mut buckets: HashMap<usize, Vec<String>> = HashMap::new();
+    for sentence in sentences {
+        let sentence = sentence.trim().to_string();
+        // Only keep sentences that can actually be typed in combat
+        if sentence.is_empty() || !sentence.chars().all(|c| (' '..='~').contains(&c)) {
+            continue;
+        }
+        buckets
+            .entry(sentence.chars().count())
+            .or_default()
+            .push(sentence);
+    }
+    // Keep the order fixed so a seed always picks the same sentence
+    for bucket in buckets.values_mut() {
+        bucket.sort();
+    }
+    buckets
+}
+
+fn read_corpus(path: &Path) -> Option<Corpus> {
+    let name = path.file_stem()?.to_string_lossy().to_string();
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            println!("Failed to read corpus {}: {}", path.display(), error);
+            return None;
+        }
+    };
+    let (name, sentences) = match path.extension()?.to_str()? {
+        "txt" => (
+            name,
+            contents
+                .lines()
+                .filter(|line| !line.starts_with(CORPUS_COMMENT))
+                .map(str::to_string)
+                .collect(),
+        ),
+        "json" => match serde_json::from_str(&contents) {
+            Ok(CorpusFile::Sentences(sentences)) => (name, sentences),
+            Ok(CorpusFile::Named { name, sentences }) => (name, sentences),
+            Err(error) => {
+                println!("Failed to parse corpus {}: {}", path.display(), error);
+                return None;
+            }
+        },
+        _ => return None,
+    };
+    let sentences = bucket_sentences(sentences);
+    if sentences.is_empty() {
+        println!("Corpus {} has no typeable sentences", path.display());
+        return None;
+    }
+    Some(Corpus { name, sentences })
+}
+
+fn load_corpora() -> Vec<Corpus> {
+    // The built-in sentences are bucketed again as a few of them can't be typed
+    let mut corpora = vec![Corpus {
+        name: BUILT_IN_CORPUS.to_string(),
+        sentences: bucket_sentences(SENTENCES.values().flatten().cloned().collect()),
+    }];
+    let mut paths: Vec<_> = match fs::read_dir(CORPUS_DIRECTORY) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .collect(),
+        Err(_) => return corpora,
+    };
+    paths.sort();
+    for path in paths {
+        if let Some(corpus) = read_corpus(&path) {
+            println!("Loaded corpus {}", corpus.name);
+            corpora.push(corpus);
+        }
+    }
+    corpora
+}
+
 pub async fn load_sentences() {
     initialize(&SENTENCES);
     println!("Sentences loaded");
+    initialize(&CORPORA);
+    println!("Corpora loaded");
+}
+
+// Fall back to the built-in sentences if the pack is no longer available
+fn corpus_sentences(corpus: &str) -> &'static HashMap<usize, Vec<String>> {
+    match CORPORA.iter().find(|c| c.name == corpus) {
+        Some(corpus) => &corpus.sentences,
+        None => &CORPORA[0].sentences,
+    }
+}
+
+// Different words from the sentences of a pack, none of them the start of another so each can
+// be recognised as soon as it is typed
+pub fn return_words<R: Rng>(corpus: &str, count: usize, rng: &mut R) -> Vec<String> {
+    choose_words(corpus_sentences(corpus), count, rng)
 }
 
-pub fn return_sentence(length: usize) -> Option<String> {
-    match SENTENCES.get(&length) {
-        Some(vector) => vector.choose(&mut rand::thread_rng()).cloned(),
-        None => None,
+// Made up words fill in for the pack's once there aren't enough of them
+fn choose_words<R: Rng>(
+    sentences: &HashMap<usize, Vec<String>>,
+    count: usize,
+    rng: &mut R,
+) -> Vec<String> {
+    let mut words: Vec<String> = sentences
+        .values()
+        .flatten()
+        .flat_map(|sentence| sentence.split_whitespace())
+        .map(|word| {
+            word.trim_matches(|c: char| !c.is_alphabetic())
+                .to_lowercase()
+        })
+        .filter(|word| {
+            (3..=7).contains(&word.len()) && word.chars().all(|c| c.is_ascii_lowercase())
+        })
+        .collect();
+    // Sorted first so the same seed always gives the same words
+    words.sort();
+    words.dedup();
+    words.shuffle(rng);
+    let made_up = std::iter::repeat_with(|| {
+        (0..4)
+            .map(|_| rng.gen_range(b'a'..=b'z') as char)
+            .collect::<String>()
+    });
+    let mut chosen: Vec<String> = Vec::new();
+    for word in words.into_iter().chain(made_up) {
+        if chosen.len() == count {
+            break;
+        }
+        if !chosen
+            .iter()
+            .any(|other| other.starts_with(&word) || word.starts_with(other.as_str()))
+        {
+            chosen.push(word);
+        }
+    }
+    chosen
+}
+
+// Picks a sentence the given filter accepts, ignoring the filter if the corpus has none it likes
+
+pub fn return_sentence<R: Rng>(
+    corpus: &str,
+    length: usize,
+    accepts: impl Fn(&str) -> bool,
+    rng: &mut R,
+) -> Option<String> {
+    let sentences = corpus_sentences(corpus);
+    let accepted = |sentence_length: &usize| -> Vec<&String> {
+        sentences[sentence_length]
+            .iter()
+            .filter(|sentence| accepts(sentence))
+            .collect()
+    };
+    if let Some(sentence) = sentences
+        .get(&length)
+        .and_then(|_| accepted(&length).choose(rng).copied())
+    {
+        return Some(sentence.clone());
+    }
+    // Packs rarely have a sentence of every length so use the closest one instead
+    let mut lengths: Vec<&usize> = sentences.keys().collect();
+    lengths.sort_by_key(|sentence_length| (sentence_length.abs_diff(length), **sentence_length));
+    match lengths
+        .iter()
+        .map(|sentence_length| accepted(sentence_length))
+        .find(|candidates| !candidates.is_empty())
+    {
+        Some(candidates) => candidates.choose(rng).map(|sentence| (*sentence).clone()),
+        None => sentences[*lengths.first()?].choose(rng).cloned(),
+    }
+}
+
+fn corpus_menu_position(index: usize, (screen_width, screen_height): (f32, f32)) -> (f32, f32) {
+    (
+        screen_width / 4.,
+        screen_height / 4. + (index as f32 + 1.) * 1.5 * f32::from(MENU_FONT_SIZE),
+    )
+}
+
+pub fn draw_corpus_menu(selected: usize) {
+    clear_background(BLACK);
+    let title = "Choose what to practice on";
+    draw_text(
+        title,
+        virtual_width() / 2. - measure_text(title, None, MENU_FONT_SIZE, 1.).width / 2.,
+        virtual_height() / 4.,
+        f32::from(MENU_FONT_SIZE),
+        WHITE,
+    );
+    for (i, corpus) in CORPORA.iter().enumerate() {
+        let (x, y) = corpus_menu_position(i, (virtual_width(), virtual_height()));
+        let num_sentences: usize = corpus.sentences.values().map(Vec::len).sum();
+        let text = format!("{}. {} ({} sentences)", i + 1, corpus.name, num_sentences);
+        let color = if i == selected { YELLOW } else { WHITE };
+        draw_text(&text, x, y, f32::from(MENU_FONT_SIZE), color);
+    }
+}
+
+// Returns the chosen corpus once the player confirms it, moving the highlight otherwise
+pub fn corpus_select(input: &impl Input, selected: &mut usize) -> Option<String> {
+    let number_keys = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+    ];
+    for (i, key) in number_keys.iter().enumerate().take(CORPORA.len()) {
+        if input.key_pressed(*key) {
+            return Some(CORPORA[i].name.clone());
+        }
+    }
+    if input.key_pressed(KeyCode::Down) {
+        *selected = (*selected + 1) % CORPORA.len();
+    }
+    if input.key_pressed(KeyCode::Up) {
+        *selected = (*selected + CORPORA.len() - 1) % CORPORA.len();
+    }
+    if input.key_pressed(KeyCode::Enter) {
+        return Some(CORPORA[*selected].name.clone());
+    }
+    if let Some((_, mouse_y)) = input.mouse_clicked() {
+        for i in 0..CORPORA.len() {
+            let (_, y) = corpus_menu_position(i, input.screen_size());
+            if mouse_y <= y && mouse_y >= y - f32::from(MENU_FONT_SIZE) {
+                return Some(CORPORA[i].name.clone());
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn a_small_pack_still_gives_a_word_for_every_neighbour() {
+        let sentences = HashMap::from([(26, vec!["The cat sat on the cattle.".to_string()])]);
+        let words = choose_words(&sentences, 6, &mut ChaCha8Rng::seed_from_u64(1));
+        assert_eq!(words.len(), 6);
+        // "cat" and "cattle" can't both be used
+        assert_eq!(
+            words
+                .iter()
+                .filter(|word| ["the", "cat", "sat", "cattle"].contains(&word.as_str()))
+                .count(),
+            3
+        );
+        for (i, word) in words.iter().enumerate() {
+            for other in &words[i + 1..] {
+                assert!(!word.starts_with(other.as_str()) && !other.starts_with(word.as_str()));
+            }
+        }
+    }
+
+    #[test]
+    fn every_bundled_corpus_loads_all_its_sentences() {
+        let sentence_counts: Vec<(&str, usize)> = CORPORA
+            .iter()
+            .skip(1)
+            .map(|corpus| {
+                (
+                    corpus.name.as_str(),
+                    corpus.sentences.values().flatten().count(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            sentence_counts,
+            vec![("rust", 14), ("Dungeon vocabulary", 8)]
+        );
+        let rust = &CORPORA[1].sentences;
+        assert!(rust
+            .values()
+            .flatten()
+            .any(|sentence| sentence.starts_with("#[derive(")));
     }
 }
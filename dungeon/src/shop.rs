@@ -0,0 +1,239 @@
+use crate::platform::{virtual_height, virtual_width, Input};
+use crate::relics::{random_relic, Relic};
+use crate::stats::CombatStats;
+use crate::treasure::{card_by_title, draw_cards, Rarity, RewardPool, CARD_KEYS};
+use macroquad::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+// Gold for a sentence typed perfectly at WPM_FOR_DOUBLE_GOLD is twice this
+const SENTENCE_GOLD: f32 = 10.;
+const BOSS_SENTENCE_GOLD: f32 = 20.;
+const WPM_FOR_DOUBLE_GOLD: f32 = 60.;
+const CHEST_GOLD: f32 = 15.;
+pub const HEAL_AMOUNT: f32 = 25.;
+const HEAL_PRICE: usize = 25;
+const COMMON_CARD_PRICE: usize = 30;
+const RARE_CARD_PRICE: usize = 60;
+const LEGENDARY_CARD_PRICE: usize = 100;
+const RELIC_PRICE: usize = 80;
+// Cards for sale on top of the heal
+const FONT_SIZE: u16 = 40;
+
+// The gold earned for finishing a sentence, faster and more accurate typing earns more
+pub fn sentence_gold(stats: &CombatStats, boss: bool) -> usize {
+    let base = if boss {
+        BOSS_SENTENCE_GOLD
+    } else {
+        SENTENCE_GOLD
+    };
+    (base * stats.corrected_accuracy * (1. + stats.wpm / WPM_FOR_DOUBLE_GOLD)).round() as usize
+}
+
+// Chests pay out more the more accurately the player has typed this run
+pub fn chest_gold(combat_stats: &[CombatStats]) -> usize {
+    let accuracy = if combat_stats.is_empty() {
+        1.
+    } else {
+        combat_stats
+            .iter()
+            .map(|stats| stats.corrected_accuracy)
+            .sum::<f32>()
+            / combat_stats.len() as f32
+    };
+    (CHEST_GOLD * accuracy).round() as usize
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub enum ShopItem {
+    Heal,
+    // The title of one of the reward cards
+    Card(String),
+    Relic(Relic),
+}
+
+impl ShopItem {
+    pub fn name(&self) -> String {
+        match self {
+            ShopItem::Heal => "Healing Salve".to_string(),
+            ShopItem::Card(title) => title.clone(),
+            ShopItem::Relic(relic) => format!("{} (relic)", relic.name()),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            ShopItem::Heal => format!("Restore {} health", HEAL_AMOUNT),
+            ShopItem::Card(title) => card_by_title(title)
+                .map(|card| card.description.clone())
+                .unwrap_or_default(),
+            ShopItem::Relic(relic) => relic.description().to_string(),
+        }
+    }
+
+    pub fn price(&self) -> usize {
+        match self {
+            ShopItem::Heal => HEAL_PRICE,
+            ShopItem::Card(title) => match card_by_title(title).map(|card| card.rarity) {
+                Some(Rarity::Legendary) => LEGENDARY_CARD_PRICE,
+                Some(Rarity::Rare) => RARE_CARD_PRICE,
+                _ => COMMON_CARD_PRICE,
+            },
+            ShopItem::Relic(_) => RELIC_PRICE,
+        }
+    }
+
+    // Relics can only be owned once
+    pub fn can_buy(&self, gold: usize, relics: &[Relic]) -> bool {
+        match self {
+            ShopItem::Relic(relic) if relics.contains(relic) => false,
+            _ => self.price() <= gold,
+        }
+    }
+}
+
+// A heal is always on sale, the cards change every level and can include ones for the whole run.
+// The relic might be one the player already has, in which case it can't be bought
+pub fn shop_stock(rng: &mut ChaCha8Rng) -> Vec<ShopItem> {
+    let mut stock = vec![ShopItem::Heal];
+    stock.extend(
+        draw_cards(&RewardPool::SHOP, rng)
+            .into_iter()
+            .map(ShopItem::Card),
+    );
+    stock.push(ShopItem::Relic(random_relic(rng)));
+    stock
+}
+
+pub enum ShopChoice {
+    Buy(usize),
+    Leave,
+}
+
+// Where the baseline of an item's line is drawn
+pub fn shop_item_position(index: usize, (screen_width, screen_height): (f32, f32)) -> (f32, f32) {
+    (
+        screen_width / 4.,
+        screen_height / 3. + index as f32 * 2.5 * f32::from(FONT_SIZE),
+    )
+}
+
+pub fn shop_select(stock: &[ShopItem], input: &impl Input) -> Option<ShopChoice> {
+    for (i, key) in CARD_KEYS.iter().enumerate().take(stock.len()) {
+        if input.key_pressed(*key) {
+            return Some(ShopChoice::Buy(i));
+        }
+    }
+    if input.key_pressed(KeyCode::Enter) {
+        return Some(ShopChoice::Leave);
+    }
+    if let Some((_, mouse_y)) = input.mouse_clicked() {
+        for i in 0..stock.len() {
+            let (_, y) = shop_item_position(i, input.screen_size());
+            if mouse_y <= y && mouse_y >= y - f32::from(FONT_SIZE) {
+                return Some(ShopChoice::Buy(i));
+            }
+        }
+    }
+    None
+}
+
+// Shown in the corner of the map
+pub fn draw_gold(gold: usize) {
+    let text = format!("Gold: {}", gold);
+    draw_text(
+        &text,
+        virtual_width() - measure_text(&text, None, 30, 1.).width - 20.,
+        65.,
+        30.,
+        BLACK,
+    );
+}
+
+pub fn draw_shop(stock: &[ShopItem], gold: usize, relics: &[Relic]) {
+    let font_size = f32::from(FONT_SIZE);
+    let (x, y) = (virtual_width() / 6., virtual_height() / 6.);
+    draw_rectangle(
+        x,
+        y,
+        virtual_width() - 2. * x,
+        virtual_height() - 2. * y,
+        Color::from_rgba(30, 20, 10, 230),
+    );
+    draw_text(
+        &format!("Shop - you have {} gold", gold),
+        virtual_width() / 4.,
+        y + font_size * 1.5,
+        font_size * 1.2,
+        GOLD,
+    );
+    if stock.is_empty() {
+        draw_text(
+            "Sold out",
+            virtual_width() / 4.,
+            virtual_height() / 3.,
+            font_size,
+            GRAY,
+        );
+    }
+    for (i, item) in stock.iter().enumerate() {
+        let (item_x, item_y) = shop_item_position(i, (virtual_width(), virtual_height()));
+        let color = if item.can_buy(gold, relics) {
+            WHITE
+        } else {
+            GRAY
+        };
+        let price = match item {
+            ShopItem::Relic(relic) if relics.contains(relic) => "owned".to_string(),
+            _ => format!("{} gold", item.price()),
+        };
+        draw_text(
+            &format!("{}. {} - {}", i + 1, item.name(), price),
+            item_x,
+            item_y,
+            font_size,
+            color,
+        );
+        draw_text(
+            &item.description(),
+            item_x + font_size,
+            item_y + font_size,
+            font_size * 0.75,
+            LIGHTGRAY,
+        );
+    }
+    let prompt = "Press a number or click an item to buy it, enter to leave";
+    draw_text(
+        prompt,
+        virtual_width() / 2. - measure_text(prompt, None, 30, 1.).width / 2.,
+        virtual_height() - y - 30.,
+        30.,
+        GRAY,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::treasure::CARDS;
+
+    #[test]
+    fn relics_can_be_bought_once() {
+        let relic = ShopItem::Relic(Relic::SeaUrchin);
+        assert!(relic.can_buy(RELIC_PRICE, &[Relic::CoralCharm]));
+        assert!(!relic.can_buy(RELIC_PRICE - 1, &[]));
+        assert!(!relic.can_buy(1000, &[Relic::SeaUrchin]));
+        assert!(ShopItem::Heal.can_buy(HEAL_PRICE, &[Relic::SeaUrchin]));
+    }
+
+    #[test]
+    fn cards_cost_more_the_rarer_they_are() {
+        let price = |rarity| {
+            let card = CARDS.iter().find(|card| card.rarity == rarity).unwrap();
+            ShopItem::Card(card.title.clone()).price()
+        };
+        assert_eq!(price(Rarity::Common), COMMON_CARD_PRICE);
+        assert_eq!(price(Rarity::Rare), RARE_CARD_PRICE);
+        assert_eq!(price(Rarity::Legendary), LEGENDARY_CARD_PRICE);
+    }
+}
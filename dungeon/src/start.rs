@@ -1 +1,177 @@
-fn draw() {}
+use crate::combat::draw_sentence;
+use crate::platform::{virtual_height, virtual_width, Input};
+use crate::stats::{summary_lines, CombatStats};
+use macroquad::prelude::*;
+
+const TITLE_FONT_SIZE: u16 = 100;
+const MENU_FONT_SIZE: u16 = 50;
+const HINT_FONT_SIZE: u16 = 30;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TitleOption {
+    NewRun,
+    Continue,
+    Settings,
+    Practice,
+    Statistics,
+    Editor,
+    Quit,
+}
+
+impl TitleOption {
+    pub const ALL: [TitleOption; 7] = [
+        TitleOption::NewRun,
+        TitleOption::Continue,
+        TitleOption::Settings,
+        TitleOption::Practice,
+        TitleOption::Statistics,
+        TitleOption::Editor,
+        TitleOption::Quit,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            TitleOption::NewRun => "New Run",
+            TitleOption::Continue => "Continue",
+            TitleOption::Settings => "Settings",
+            TitleOption::Practice => "Practice Mode",
+            TitleOption::Statistics => "Statistics",
+            TitleOption::Editor => "Level Editor",
+            TitleOption::Quit => "Quit",
+        }
+    }
+
+    // Continue can only be chosen when there is a run to continue
+    fn enabled(&self, save_found: bool) -> bool {
+        *self != TitleOption::Continue || save_found
+    }
+}
+
+// Where the baseline of an option's text is drawn
+pub fn title_menu_position(index: usize, (screen_width, screen_height): (f32, f32)) -> (f32, f32) {
+    (
+        screen_width / 2.,
+        screen_height / 2.5 + index as f32 * 1.6 * f32::from(MENU_FONT_SIZE),
+    )
+}
+
+pub fn draw_title_screen(selected: usize, save_found: bool) {
+    clear_background(BLACK);
+    let title = "Dungeon Explorer";
+    draw_text(
+        title,
+        virtual_width() / 2. - measure_text(title, None, TITLE_FONT_SIZE, 1.).width / 2.,
+        virtual_height() / 5.,
+        f32::from(TITLE_FONT_SIZE),
+        WHITE,
+    );
+    for (i, option) in TitleOption::ALL.iter().enumerate() {
+        let (x, y) = title_menu_position(i, (virtual_width(), virtual_height()));
+        let color = if !option.enabled(save_found) {
+            DARKGRAY
+        } else if i == selected {
+            YELLOW
+        } else {
+            WHITE
+        };
+        let text = if i == selected {
+            format!("> {} <", option.name())
+        } else {
+            option.name().to_string()
+        };
+        draw_text(
+            &text,
+            x - measure_text(&text, None, MENU_FONT_SIZE, 1.).width / 2.,
+            y,
+            f32::from(MENU_FONT_SIZE),
+            color,
+        );
+    }
+    let hint = "Use the arrow keys and enter or click an option";
+    draw_text(
+        hint,
+        virtual_width() / 2. - measure_text(hint, None, HINT_FONT_SIZE, 1.).width / 2.,
+        virtual_height() - 60.,
+        f32::from(HINT_FONT_SIZE),
+        GRAY,
+    );
+}
+
+// Moves the highlight past anything disabled and returns the option once it is chosen
+pub fn title_select(
+    input: &impl Input,
+    selected: &mut usize,
+    save_found: bool,
+) -> Option<TitleOption> {
+    let num_options = TitleOption::ALL.len();
+    let step = |selected: usize, forwards: bool| {
+        let mut next = selected;
+        loop {
+            next = if forwards {
+                (next + 1) % num_options
+            } else {
+                (next + num_options - 1) % num_options
+            };
+            if TitleOption::ALL[next].enabled(save_found) {
+                return next;
+            }
+        }
+    };
+    if !TitleOption::ALL[*selected].enabled(save_found) {
+        *selected = step(*selected, true);
+    }
+    if input.key_pressed(KeyCode::Down) {
+        *selected = step(*selected, true);
+    }
+    if input.key_pressed(KeyCode::Up) {
+        *selected = step(*selected, false);
+    }
+    if input.key_pressed(KeyCode::Enter) {
+        return Some(TitleOption::ALL[*selected]);
+    }
+    if let Some((_, mouse_y)) = input.mouse_clicked() {
+        for (i, option) in TitleOption::ALL.iter().enumerate() {
+            let (_, y) = title_menu_position(i, input.screen_size());
+            if option.enabled(save_found)
+                && mouse_y <= y
+                && mouse_y >= y - f32::from(MENU_FONT_SIZE)
+            {
+                *selected = i;
+                return Some(*option);
+            }
+        }
+    }
+    None
+}
+
+// Practice is typing without anything fighting back, the stats of the last sentence are shown
+pub fn draw_practice(sentence: &[char], user_sentence: &[char], last_stats: Option<&CombatStats>) {
+    draw_rectangle(0., 0., virtual_width(), virtual_height(), DARKGRAY);
+    draw_sentence(sentence, user_sentence, &DARKGRAY);
+    if let Some(stats) = last_stats {
+        draw_text(
+            "Last sentence",
+            80.,
+            virtual_height() / 2.,
+            f32::from(MENU_FONT_SIZE),
+            WHITE,
+        );
+        for (i, line) in summary_lines(stats).iter().enumerate() {
+            draw_text(
+                line,
+                80.,
+                virtual_height() / 2. + (i + 1) as f32 * f32::from(MENU_FONT_SIZE),
+                f32::from(HINT_FONT_SIZE) * 1.3,
+                LIGHTGRAY,
+            );
+        }
+    }
+    let hint = "Press escape to pause or go back to the title screen";
+    draw_text(
+        hint,
+        virtual_width() / 2. - measure_text(hint, None, HINT_FONT_SIZE, 1.).width / 2.,
+        virtual_height() - 60.,
+        f32::from(HINT_FONT_SIZE),
+        GRAY,
+    );
+}
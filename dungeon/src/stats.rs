@@ -0,0 +1,343 @@
+use crate::platform::{virtual_height, virtual_width};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+const SUMMARY_FONT_SIZE: u16 = 40;
+const NUM_MISSED_CHARACTERS: usize = 5;
+const HEATMAP_KEY_SIZE: f32 = 50.;
+// The keys of a US keyboard as typed without shift, the space bar goes underneath
+const KEYBOARD_ROWS: [&str; 4] = [
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+// What each key types with shift held, in the same order as the rows
+const SHIFTED_ROWS: [&str; 4] = [
+    "~!@#$%^&*()_+",
+    "QWERTYUIOP{}|",
+    "ASDFGHJKL:\"",
+    "ZXCVBNM<>?",
+];
+
+// What the player did with the keyboard during a frame of combat
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TypingEvent {
+    // A character was typed at the given position of the sentence
+    Typed(char, usize),
+    Deleted,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct CombatStats {
+    pub wpm: f32,
+    // Fraction of all keystrokes that matched the sentence
+    pub raw_accuracy: f32,
+    // Fraction of the sentence typed right the first time, fixed mistakes still count
+    pub corrected_accuracy: f32,
+    pub backspaces: usize,
+    // In seconds
+    pub time_to_first_keystroke: f32,
+    // Mistakes made against each character of the sentence
+    pub char_errors: BTreeMap<char, usize>,
+    // Mistakes made against each pair of characters, keyed by the pair
+    #[serde(default)]
+    pub bigram_errors: BTreeMap<String, usize>,
+}
+
+// Watches the keystrokes of a single combat to produce its CombatStats
+pub struct TypingTracker {
+    started: Instant,
+    first_keystroke: Option<Instant>,
+    keystrokes: usize,
+    correct_keystrokes: usize,
+    backspaces: usize,
+    mistyped_positions: BTreeSet<usize>,
+    char_errors: BTreeMap<char, usize>,
+    bigram_errors: BTreeMap<String, usize>,
+}
+
+impl TypingTracker {
+    pub fn new(now: Instant) -> Self {
+        TypingTracker {
+            started: now,
+            first_keystroke: None,
+            keystrokes: 0,
+            correct_keystrokes: 0,
+            backspaces: 0,
+            mistyped_positions: BTreeSet::new(),
+            char_errors: BTreeMap::new(),
+            bigram_errors: BTreeMap::new(),
+        }
+    }
+
+    // Time spent paused doesn't count towards the typing speed
+    pub fn shift(&mut self, paused_for: Duration) {
+        self.started += paused_for;
+        if let Some(first_keystroke) = &mut self.first_keystroke {
+            *first_keystroke += paused_for;
+        }
+    }
+
+    pub fn record(&mut self, event: TypingEvent, sentence: &[char], now: Instant) {
+        self.first_keystroke.get_or_insert(now);
+        match event {
+            TypingEvent::Typed(c, position) => {
+                self.keystrokes += 1;
+                match sentence.get(position) {
+                    Some(expected) if *expected == c => self.correct_keystrokes += 1,
+                    Some(expected) => {
+                        self.mistyped_positions.insert(position);
+                        *self.char_errors.entry(*expected).or_default() += 1;
+                        if position > 0 {
+                            let bigram: String = sentence[position - 1..=position].iter().collect();
+                            *self.bigram_errors.entry(bigram).or_default() += 1;
+                        }
+                    }
+                    // Typing past the end of the sentence isn't a mistake on any character
+                    None => (),
+                }
+            }
+            TypingEvent::Deleted => self.backspaces += 1,
+        }
+    }
+
+    // The speed so far, counting only the given number of correct characters
+    pub fn current_wpm(&self, correct_characters: usize, now: Instant) -> f32 {
+        let minutes = match self.first_keystroke {
+            Some(first_keystroke) => {
+                now.saturating_duration_since(first_keystroke).as_secs_f32() / 60.
+            }
+            None => 0.,
+        };
+        if minutes > 0. {
+            correct_characters as f32 / 5. / minutes
+        } else {
+            0.
+        }
+    }
+
+    // Pass only the part of the sentence that was typed if the enemy fell before the end of it
+    pub fn finish(&self, sentence: &[char], now: Instant) -> CombatStats {
+        let first_keystroke = self.first_keystroke.unwrap_or(now);
+        let minutes = now.saturating_duration_since(first_keystroke).as_secs_f32() / 60.;
+        CombatStats {
+            // A word is counted as five characters
+            wpm: if minutes > 0. {
+                sentence.len() as f32 / 5. / minutes
+            } else {
+                0.
+            },
+            raw_accuracy: if self.keystrokes > 0 {
+                self.correct_keystrokes as f32 / self.keystrokes as f32
+            } else {
+                1.
+            },
+            corrected_accuracy: if sentence.is_empty() {
+                1.
+            } else {
+                1. - self.mistyped_positions.range(..sentence.len()).count() as f32
+                    / sentence.len() as f32
+            },
+            backspaces: self.backspaces,
+            time_to_first_keystroke: first_keystroke
+                .saturating_duration_since(self.started)
+                .as_secs_f32(),
+            char_errors: self.char_errors.clone(),
+            bigram_errors: self.bigram_errors.clone(),
+        }
+    }
+}
+
+pub fn character_name(c: char) -> String {
+    if c == ' ' {
+        "space".to_string()
+    } else {
+        format!("'{}'", c)
+    }
+}
+
+// The characters with the most mistakes, worst first
+pub fn most_missed(char_errors: &BTreeMap<char, usize>) -> String {
+    let mut errors: Vec<(&char, &usize)> = char_errors.iter().collect();
+    errors.sort_by(|a, b| b.1.cmp(a.1));
+    if errors.is_empty() {
+        return "none".to_string();
+    }
+    errors
+        .iter()
+        .take(NUM_MISSED_CHARACTERS)
+        .map(|(c, count)| format!("{} x{}", character_name(**c), count))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// The key a character is typed with, shifted characters share a key with the unshifted one
+pub fn keyboard_key(c: char) -> Option<char> {
+    if c == ' ' {
+        return Some(' ');
+    }
+    KEYBOARD_ROWS
+        .iter()
+        .zip(SHIFTED_ROWS)
+        .find_map(|(row, shifted)| {
+            row.chars()
+                .zip(shifted.chars())
+                .find(|(key, shifted)| *key == c || *shifted == c)
+        })
+        .map(|(key, _)| key)
+}
+
+// Adds up the mistakes made on each key
+pub fn key_errors(char_errors: &BTreeMap<char, usize>) -> BTreeMap<char, usize> {
+    let mut errors = BTreeMap::new();
+    for (c, count) in char_errors {
+        if let Some(key) = keyboard_key(*c) {
+            *errors.entry(key).or_default() += count;
+        }
+    }
+    errors
+}
+
+// A keyboard with every key shaded from white to red by how often it was missed
+fn draw_error_heatmap(char_errors: &BTreeMap<char, usize>, x: f32, y: f32) {
+    let errors = key_errors(char_errors);
+    let most = errors.values().copied().max().unwrap_or(0).max(1) as f32;
+    let draw_key = |label: &str, key: char, key_x: f32, key_y: f32, width: f32| {
+        let heat = *errors.get(&key).unwrap_or(&0) as f32 / most;
+        let color = Color::new(1., 1. - heat * 0.8, 1. - heat * 0.8, 1.);
+        let size = HEATMAP_KEY_SIZE * 0.9;
+        draw_rectangle(key_x, key_y, width - HEATMAP_KEY_SIZE * 0.1, size, color);
+        draw_rectangle_lines(key_x, key_y, width - HEATMAP_KEY_SIZE * 0.1, size, 2., GRAY);
+        draw_text(label, key_x + 8., key_y + size * 0.7, size * 0.6, BLACK);
+    };
+    for (i, row) in KEYBOARD_ROWS.iter().enumerate() {
+        // Each row starts a little further right, like a real keyboard
+        let row_x = x + i as f32 * HEATMAP_KEY_SIZE * 0.4;
+        let row_y = y + i as f32 * HEATMAP_KEY_SIZE;
+        for (j, key) in row.chars().enumerate() {
+            let key_x = row_x + j as f32 * HEATMAP_KEY_SIZE;
+            draw_key(&key.to_string(), key, key_x, row_y, HEATMAP_KEY_SIZE);
+        }
+    }
+    draw_key(
+        "space",
+        ' ',
+        x + HEATMAP_KEY_SIZE * 3.,
+        y + KEYBOARD_ROWS.len() as f32 * HEATMAP_KEY_SIZE,
+        HEATMAP_KEY_SIZE * 6.,
+    );
+}
+
+pub fn summary_lines(stats: &CombatStats) -> Vec<String> {
+    vec![
+        format!("Words per minute: {:.1}", stats.wpm),
+        format!(
+            "Accuracy: {:.0}% raw, {:.0}% corrected",
+            stats.raw_accuracy * 100.,
+            stats.corrected_accuracy * 100.
+        ),
+        format!("Backspaces: {}", stats.backspaces),
+        format!(
+            "Time to first keystroke: {:.1}s",
+            stats.time_to_first_keystroke
+        ),
+        format!("Most missed: {}", most_missed(&stats.char_errors)),
+    ]
+}
+
+// Averages every combat of a run into a few lines for the end screens
+pub fn run_summary_lines(combats: &[CombatStats]) -> Vec<String> {
+    if combats.is_empty() {
+        return Vec::new();
+    }
+    let num_combats = combats.len() as f32;
+    let average =
+        |value: fn(&CombatStats) -> f32| combats.iter().map(value).sum::<f32>() / num_combats;
+    let mut char_errors: BTreeMap<char, usize> = BTreeMap::new();
+    for combat in combats {
+        for (c, count) in &combat.char_errors {
+            *char_errors.entry(*c).or_default() += count;
+        }
+    }
+    vec![
+        format!(
+            "Average speed {:.1} wpm, accuracy {:.0}% raw and {:.0}% corrected, {} backspaces",
+            average(|stats| stats.wpm),
+            average(|stats| stats.raw_accuracy) * 100.,
+            average(|stats| stats.corrected_accuracy) * 100.,
+            combats.iter().map(|stats| stats.backspaces).sum::<usize>()
+        ),
+        format!("Most missed: {}", most_missed(&char_errors)),
+    ]
+}
+
+pub fn draw_combat_summary(stats: &CombatStats) {
+    let font_size = f32::from(SUMMARY_FONT_SIZE);
+    let lines = summary_lines(stats);
+    let heatmap_height = HEATMAP_KEY_SIZE * (KEYBOARD_ROWS.len() + 1) as f32;
+    let (width, height) = (
+        virtual_width() / 2.,
+        font_size * (lines.len() as f32 + 4.) + heatmap_height,
+    );
+    let (x, y) = (
+        virtual_width() / 2. - width / 2.,
+        virtual_height() / 2. - height / 2.,
+    );
+    draw_rectangle(x, y, width, height, WHITE);
+    draw_rectangle_lines(x, y, width, height, 5., BLACK);
+    let title = "Enemy defeated!";
+    draw_text(
+        title,
+        virtual_width() / 2. - measure_text(title, None, SUMMARY_FONT_SIZE, 1.).width / 2.,
+        y + font_size * 1.5,
+        font_size,
+        BLACK,
+    );
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            line,
+            x + 40.,
+            y + font_size * (i as f32 + 2.75),
+            font_size,
+            BLACK,
+        );
+    }
+    // The widest row is 13 keys plus its offset
+    let heatmap_width = HEATMAP_KEY_SIZE * 13.4;
+    draw_error_heatmap(
+        &stats.char_errors,
+        virtual_width() / 2. - heatmap_width / 2.,
+        y + font_size * (lines.len() as f32 + 2.25),
+    );
+    let prompt = "Press enter to continue";
+    draw_text(
+        prompt,
+        virtual_width() / 2. - measure_text(prompt, None, SUMMARY_FONT_SIZE, 1.).width / 2.,
+        y + height - font_size / 2.,
+        font_size,
+        GRAY,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mistakes_are_added_up_by_the_key_they_are_typed_with() {
+        assert_eq!(keyboard_key('a'), Some('a'));
+        assert_eq!(keyboard_key('A'), Some('a'));
+        assert_eq!(keyboard_key('!'), Some('1'));
+        assert_eq!(keyboard_key('"'), Some('\''));
+        assert_eq!(keyboard_key(' '), Some(' '));
+        assert_eq!(keyboard_key('é'), None);
+        let char_errors = BTreeMap::from([('e', 2), ('E', 1), ('?', 4), ('/', 1)]);
+        assert_eq!(
+            key_errors(&char_errors),
+            BTreeMap::from([('e', 3), ('/', 5)])
+        );
+    }
+}
@@ -1,15 +1,94 @@
+use crate::effects::Effect;
+use crate::platform::{virtual_height, virtual_width, Input};
+use ::rand::seq::SliceRandom;
 use lazy_static::{initialize, lazy_static};
 use macroquad::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 
 const GOLDEN_RATIO: f32 = 1.618_034;
-#[derive(Clone)]
-pub enum CardType {
-    TempHeal,
-    TempWordsReduce,
-    TempDamageReduction,
-    PermHeal,
-    PermWordsReduce,
-    PermDamageReduction,
+const CARDS_PER_ROW: usize = 3;
+// Cards refer to their image by name so they can be built without a window
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CardImage {
+    Armour,
+    CrabFood,
+    Supplements,
+    StrengthenedExoskeleton,
+    BulkUp,
+    SharperClaws,
+}
+
+impl CardImage {
+    pub fn texture(&self) -> Texture2D {
+        match self {
+            CardImage::Armour => *ARMOUR_TEXTURE,
+            CardImage::CrabFood => *CRAB_FOOD,
+            CardImage::Supplements => *SUPPLEMENTS,
+            CardImage::StrengthenedExoskeleton => *STRENGTHENED_EXOSKELETON,
+            CardImage::BulkUp => *BULK_UP,
+            CardImage::SharperClaws => *SHARPER_CLAWS,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Rarity {
+    Common,
+    Rare,
+    Legendary,
+}
+
+impl Rarity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rarity::Common => "Common",
+            Rarity::Rare => "Rare",
+            Rarity::Legendary => "Legendary",
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Rarity::Common => DARKGRAY,
+            Rarity::Rare => Color::from_rgba(30, 110, 230, 255),
+            Rarity::Legendary => Color::from_rgba(230, 160, 0, 255),
+        }
+    }
+}
+
+// What a chest, boss or shop draws its cards from
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RewardPool {
+    // How many cards are offered
+    pub cards: usize,
+    // How likely a common, rare or legendary card is to be drawn
+    pub weights: [u32; 3],
+    // Whether cards that last the whole run can be drawn
+    pub permanent: bool,
+}
+
+impl RewardPool {
+    pub const CHEST: RewardPool = RewardPool {
+        cards: 3,
+        weights: [70, 25, 5],
+        permanent: false,
+    };
+    pub const SHOP: RewardPool = RewardPool {
+        cards: 3,
+        weights: [40, 45, 15],
+        permanent: true,
+    };
+
+    fn weight(&self, rarity: Rarity) -> u32 {
+        match rarity {
+            Rarity::Common => self.weights[0],
+            Rarity::Rare => self.weights[1],
+            Rarity::Legendary => self.weights[2],
+        }
+    }
 }
 
 lazy_static! {
@@ -37,78 +116,50 @@ lazy_static! {
         include_bytes!("../assets/sharper-claws.png"),
         Some(ImageFormat::Png),
     );
-    pub static ref TEMP_CARDS: [Card; 3] = [
-        Card {
-            title: "Stronger Armor".to_string(),
-            card_type: CardType::TempDamageReduction,
-            card_width: 300.,
-            card_height: 300. * GOLDEN_RATIO,
-            image: *ARMOUR_TEXTURE,
-            description: "Take 1 less damage from each enemy attack for the next brawl."
-                .to_string(),
-        },
-        Card {
-            title: "Crab Food".to_string(),
-            card_type: CardType::TempHeal,
-            card_width: 300.,
-            card_height: 300. * GOLDEN_RATIO,
-            image: *CRAB_FOOD,
-            description: "Restore 40 health".to_string(),
-        },
-        Card {
-            title: "Supplements".to_string(),
-            card_type: CardType::TempDamageReduction,
-            card_width: 300.,
-            card_height: 300. * GOLDEN_RATIO,
-            image: *SUPPLEMENTS,
-            description: "You need to type 10 fewer characters in the next brawl".to_string(),
-        },
-    ];
-    pub static ref PERM_CARDS: [Card; 3] = [
-        Card {
-            title: "Stronger Carapace".to_string(),
-            card_type: CardType::PermDamageReduction,
-            card_width: 300.,
-            card_height: 300. * GOLDEN_RATIO,
-            image: *STRENGTHENED_EXOSKELETON,
-            description: "You take 5% less damage from enemy attacks".to_string(),
-        },
-        Card {
-            title: "Bulked Up".to_string(),
-            card_type: CardType::PermHeal,
-            card_width: 300.,
-            card_height: 300. * GOLDEN_RATIO,
-            image: *BULK_UP,
-            description: "Increases your max health by 10%.".to_string(),
-        },
-        Card {
-            title: "Sharpened Chelae".to_string(),
-            card_type: CardType::PermWordsReduce,
-            card_width: 300.,
-            card_height: 300. * GOLDEN_RATIO,
-            image: *SHARPER_CLAWS,
-            description: "You need 10% fewer characters to defeat any enemy".to_string(),
-        },
-    ];
+    // Every card that can be offered as a reward or sold in a shop, see assets/cards.json
+    pub static ref CARDS: Vec<Card> =
+        serde_json::from_str(include_str!("../assets/cards.json")).unwrap();
 }
 pub async fn load_treasure_images() {
-    initialize(&TEMP_CARDS);
-    println!("Temporary cards loaded");
-    initialize(&PERM_CARDS);
-    println!("Permanent cards loaded");
+    initialize(&ARMOUR_TEXTURE);
+    initialize(&CRAB_FOOD);
+    initialize(&SUPPLEMENTS);
+    initialize(&STRENGTHENED_EXOSKELETON);
+    initialize(&BULK_UP);
+    initialize(&SHARPER_CLAWS);
+    println!("Card images loaded");
+    initialize(&CARDS);
+    println!("Cards loaded");
 }
 
-#[derive(Clone)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Card {
     pub title: String,
-    pub card_type: CardType,
-    pub image: Texture2D,
+    pub image: CardImage,
+    pub rarity: Rarity,
     pub description: String,
+    // Applied in order when the card is chosen
+    pub effects: Vec<Effect>,
+    #[serde(skip, default = "Card::width")]
     pub card_width: f32,
+    #[serde(skip, default = "Card::height")]
     pub card_height: f32,
 }
 
 impl Card {
+    fn width() -> f32 {
+        300.
+    }
+
+    fn height() -> f32 {
+        Card::width() * GOLDEN_RATIO
+    }
+
+    // Permanent cards are only offered after beating a boss or in shops
+    pub fn permanent(&self) -> bool {
+        self.effects.iter().any(|effect| effect.permanent())
+    }
+
     pub fn draw_card(&self, x: f32, y: f32) {
         draw_rectangle(x, y, self.card_width, 0.15 * self.card_height, WHITE);
         draw_rectangle_lines(x, y, self.card_width, 0.15 * self.card_height, 5., BLACK);
@@ -139,7 +190,7 @@ impl Card {
         );
 
         draw_texture_ex(
-            self.image,
+            self.image.texture(),
             x,
             y + 0.15 * self.card_height,
             WHITE,
@@ -155,7 +206,22 @@ impl Card {
 
         draw_rectangle_lines(x, text_box_y, self.card_width, text_box_height, 5., BLACK);
         self.add_description(x + 20., text_box_y + 20., self.card_width - 20.);
-        draw_rectangle_lines(x, y, self.card_width, self.card_height, 5., BLACK);
+        let rarity = self.rarity.name();
+        draw_text(
+            rarity,
+            x + self.card_width - measure_text(rarity, None, 24, 1.).width - 15.,
+            y + self.card_height - 15.,
+            24.,
+            self.rarity.color(),
+        );
+        draw_rectangle_lines(
+            x,
+            y,
+            self.card_width,
+            self.card_height,
+            10.,
+            self.rarity.color(),
+        );
     }
 
     pub fn add_description(&self, x: f32, y: f32, width: f32) {
@@ -198,17 +264,251 @@ impl Card {
     }
 }
 
-pub fn card_select(cards_and_coords: &[(Card, (f32, f32))]) -> Option<&Card> {
-    if is_mouse_button_pressed(MouseButton::Left) {
-        let (x_pos, y_pos) = mouse_position();
-        for (card, (x, y)) in cards_and_coords.iter() {
+pub fn card_by_title(title: &str) -> Option<&'static Card> {
+    CARDS.iter().find(|card| card.title == title)
+}
+
+// Draws different cards at random, weighted by rarity. Chests only offer temporary cards
+pub(crate) fn draw_cards(reward_pool: &RewardPool, rng: &mut ChaCha8Rng) -> Vec<String> {
+    let mut pool: Vec<&Card> = CARDS
+        .iter()
+        .filter(|card| reward_pool.permanent || !card.permanent())
+        .collect();
+    let mut drawn = Vec::new();
+    while drawn.len() < reward_pool.cards {
+        let Ok(card) = pool.choose_weighted(rng, |card| reward_pool.weight(card.rarity)) else {
+            break;
+        };
+        let title = card.title.clone();
+        pool.retain(|card| card.title != title);
+        drawn.push(title);
+    }
+    drawn
+}
+
+pub fn offered_cards(titles: &[String]) -> Vec<Card> {
+    titles
+        .iter()
+        .filter_map(|title| card_by_title(title))
+        .cloned()
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RewardChoice {
+    Card(usize),
+    Reroll,
+    Skip,
+}
+
+// The reroll and skip buttons under the cards
+pub fn reward_buttons((screen_width, screen_height): (f32, f32)) -> [(RewardChoice, Rect); 2] {
+    let (width, height) = (360., 70.);
+    let y = screen_height - 2.2 * height;
+    [
+        (
+            RewardChoice::Reroll,
+            Rect::new(screen_width / 2. - width * 1.1, y, width, height),
+        ),
+        (
+            RewardChoice::Skip,
+            Rect::new(screen_width / 2. + width * 0.1, y, width, height),
+        ),
+    ]
+}
+
+pub fn draw_reward_buttons(reroll_price: usize, gold: usize) {
+    for (choice, rect) in reward_buttons((virtual_width(), virtual_height())) {
+        let (text, enabled) = match choice {
+            RewardChoice::Reroll => (
+                format!("R: reroll ({} gold)", reroll_price),
+                gold >= reroll_price,
+            ),
+            _ => ("S: skip".to_string(), true),
+        };
+        draw_rectangle(rect.x, rect.y, rect.w, rect.h, WHITE);
+        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 5., BLACK);
+        let size = measure_text(&text, None, 36, 1.);
+        draw_text(
+            &text,
+            rect.x + rect.w / 2. - size.width / 2.,
+            rect.y + rect.h / 2. + size.height / 2.,
+            36.,
+            if enabled { BLACK } else { GRAY },
+        );
+    }
+}
+
+// Lays the cards out in rows of three in the middle of the screen
+pub fn reward_layout(
+    cards: &[Card],
+    (screen_width, screen_height): (f32, f32),
+) -> Vec<(Card, (f32, f32))> {
+    let num_rows = cards.len().div_ceil(CARDS_PER_ROW);
+    cards
+        .iter()
+        .enumerate()
+        .map(|(i, card)| {
+            let (row, column) = (i / CARDS_PER_ROW, i % CARDS_PER_ROW);
+            let row_length = (cards.len() - row * CARDS_PER_ROW).min(CARDS_PER_ROW);
+            let middle_column = (row_length as f32 - 1.) / 2.;
+            let middle_row = (num_rows as f32 - 1.) / 2.;
+            (
+                card.clone(),
+                (
+                    screen_width / 2. + card.card_width * 1.2 * (column as f32 - middle_column)
+                        - card.card_width / 2.,
+                    screen_height / 2. + card.card_height * 1.03 * (row as f32 - middle_row)
+                        - card.card_height / 2.,
+                ),
+            )
+        })
+        .collect()
+}
+
+// The number keys choose cards in the order they are laid out
+pub const CARD_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
+// Shows which number key chooses the card drawn at x, y
+pub fn draw_card_key(index: usize, x: f32, y: f32) {
+    let radius = 24.;
+    draw_circle(x, y, radius, BLACK);
+    let text = (index + 1).to_string();
+    let size = measure_text(&text, None, 36, 1.);
+    draw_text(&text, x - size.width / 2., y + size.height / 2., 36., WHITE);
+}
+
+pub fn card_select(
+    cards_and_coords: &[(Card, (f32, f32))],
+    input: &impl Input,
+) -> Option<RewardChoice> {
+    for (i, key) in CARD_KEYS.iter().enumerate().take(cards_and_coords.len()) {
+        if input.key_pressed(*key) {
+            return Some(RewardChoice::Card(i));
+        }
+    }
+    if input.key_pressed(KeyCode::R) {
+        return Some(RewardChoice::Reroll);
+    }
+    if input.key_pressed(KeyCode::S) {
+        return Some(RewardChoice::Skip);
+    }
+    if let Some((x_pos, y_pos)) = input.mouse_clicked() {
+        for (i, (card, (x, y))) in cards_and_coords.iter().enumerate() {
             if (x_pos >= *x && x_pos <= *x + card.card_width)
                 && (y_pos >= *y && y_pos <= *y + card.card_height)
             {
-                return Some(card);
+                return Some(RewardChoice::Card(i));
+            }
+        }
+        for (choice, rect) in reward_buttons(input.screen_size()) {
+            if rect.contains(Vec2::new(x_pos, y_pos)) {
+                return Some(choice);
             }
         }
     }
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::Lasting;
+    use regex::Regex;
+    use std::collections::HashSet;
+
+    // What a card's description has to say about each of its effects
+    fn effect_phrases(effect: &Effect) -> (String, Option<Lasting>) {
+        let percent = |multiplier: f32| ((multiplier - 1.).abs() * 100.).round();
+        let less = |multiplier: f32| if multiplier < 1. { "less" } else { "more" };
+        match *effect {
+            Effect::Heal { amount } => (format!("restore {} health", amount), None),
+            Effect::MaxHealth { multiplier } => (
+                format!("max health by {}%", percent(multiplier)),
+                Some(Lasting::Run),
+            ),
+            Effect::Armour { amount, lasting } => {
+                (format!("{} less damage", amount), Some(lasting))
+            }
+            Effect::DamageTaken {
+                multiplier,
+                lasting,
+            } => (
+                format!("{}% {} damage", percent(multiplier), less(multiplier)),
+                Some(lasting),
+            ),
+            Effect::SentenceLength {
+                multiplier,
+                lasting,
+            } => (
+                format!(
+                    "{}% {} characters",
+                    percent(multiplier),
+                    if multiplier < 1. { "fewer" } else { "more" }
+                ),
+                Some(lasting),
+            ),
+            Effect::AttackInterval {
+                multiplier,
+                lasting,
+            } => (
+                format!(
+                    "{}% {} between attacks",
+                    percent(multiplier),
+                    if multiplier > 1. { "longer" } else { "shorter" }
+                ),
+                Some(lasting),
+            ),
+        }
+    }
+
+    #[test]
+    fn every_card_description_matches_its_effects() {
+        let number = Regex::new(r"\d+(\.\d+)?").unwrap();
+        let mut titles = HashSet::new();
+        for card in CARDS.iter() {
+            assert!(titles.insert(&card.title), "{} is in twice", card.title);
+            assert!(!card.effects.is_empty(), "{} does nothing", card.title);
+            let description = card.description.to_lowercase();
+            let mut phrases = Vec::new();
+            for effect in &card.effects {
+                let (phrase, lasting) = effect_phrases(effect);
+                assert!(
+                    description.contains(&phrase),
+                    "{} should say \"{}\"",
+                    card.title,
+                    phrase
+                );
+                if let Some(lasting) = lasting {
+                    assert_eq!(
+                        description.contains("next brawl"),
+                        lasting == Lasting::NextBrawl,
+                        "{} says the wrong thing about how long it lasts",
+                        card.title
+                    );
+                }
+                phrases.push(phrase);
+            }
+            // Every number in the description has to come from an effect
+            for found in number.find_iter(&description) {
+                assert!(
+                    phrases.iter().any(|phrase| phrase.contains(found.as_str())),
+                    "{} mentions {} but no effect does",
+                    card.title,
+                    found.as_str()
+                );
+            }
+        }
+    }
+}